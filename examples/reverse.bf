@@ -0,0 +1,2 @@
+Print the input reversed up to EOF or a zero byte
+>,[>,]<[.<]
@@ -0,0 +1,176 @@
+//! Tree form of a compiled program. Loops are nested nodes instead of jump indices, so tools
+//! working on the structure of a program (linters, transformations, ...) don't have to match
+//! brackets again.
+
+use crate::location::*;
+use crate::program::*;
+
+/// Sequence of nodes
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Block(pub Vec<Node>);
+
+/// Node of the syntax tree. Every node keeps the location of its command in the source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    Right(Location),
+    Left(Location),
+    Inc(Location),
+    Dec(Location),
+    Read(Location),
+    Write(Location),
+    /// Loop
+    ///
+    /// - `Block` is the loop body
+    /// - first `Location` is the location of '['
+    /// - second `Location` is the location of ']'
+    Loop(Block, Location, Location),
+}
+
+impl Node {
+    /// Location of the node. For loops, it is the location of '['.
+    pub fn location(&self) -> Location {
+        match self {
+            Node::Right(loc)
+            | Node::Left(loc)
+            | Node::Inc(loc)
+            | Node::Dec(loc)
+            | Node::Read(loc)
+            | Node::Write(loc)
+            | Node::Loop(_, loc, _) => *loc,
+        }
+    }
+}
+
+impl Block {
+    /// Accept `visitor` on every node of the block.
+    pub fn accept<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        visitor.visit_block(self);
+    }
+
+    /// Accept `visitor` on every node of the block, allowing it to modify the nodes.
+    pub fn accept_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        visitor.visit_block_mut(self);
+    }
+
+    fn from_commands(program: &Program, start: usize, end: usize) -> Block {
+        let mut nodes = Vec::new();
+        let mut i = start;
+        while i < end {
+            let loc = program.locations[i];
+            nodes.push(match program.commands[i] {
+                Command::Right => Node::Right(loc),
+                Command::Left => Node::Left(loc),
+                Command::Inc => Node::Inc(loc),
+                Command::Dec => Node::Dec(loc),
+                Command::Read => Node::Read(loc),
+                Command::Write => Node::Write(loc),
+                Command::Open(close) => {
+                    let body = Block::from_commands(program, i + 1, close);
+                    let node = Node::Loop(body, loc, program.locations[close]);
+                    i = close;
+                    node
+                }
+                Command::Close(_) | Command::Halt => break,
+            });
+            i += 1;
+        }
+        Block(nodes)
+    }
+
+    fn to_commands(&self, commands: &mut Vec<Command>, locations: &mut Vec<Location>) {
+        for node in &self.0 {
+            let command = match node {
+                Node::Right(_) => Command::Right,
+                Node::Left(_) => Command::Left,
+                Node::Inc(_) => Command::Inc,
+                Node::Dec(_) => Command::Dec,
+                Node::Read(_) => Command::Read,
+                Node::Write(_) => Command::Write,
+                Node::Loop(body, open, close) => {
+                    let start = commands.len();
+                    commands.push(Command::Open(0));
+                    locations.push(*open);
+                    body.to_commands(commands, locations);
+                    commands[start] = Command::Open(commands.len());
+                    commands.push(Command::Close(start));
+                    locations.push(*close);
+                    continue;
+                }
+            };
+            commands.push(command);
+            locations.push(node.location());
+        }
+    }
+}
+
+impl From<&Program> for Block {
+    fn from(program: &Program) -> Self {
+        Block::from_commands(program, 0, program.commands.len())
+    }
+}
+
+impl From<&Block> for Program {
+    fn from(block: &Block) -> Self {
+        let mut commands = Vec::new();
+        let mut locations = Vec::new();
+        block.to_commands(&mut commands, &mut locations);
+
+        commands.push(Command::Halt);
+        locations.push(Location::default());
+        Program {
+            commands,
+            locations,
+        }
+    }
+}
+
+/// Visitor over the syntax tree. By default, it walks every node, descending into loop bodies.
+pub trait Visitor {
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_node(&mut self, node: &Node) {
+        walk_node(self, node);
+    }
+}
+
+/// Visit every node of `block`.
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &Block) {
+    for node in &block.0 {
+        visitor.visit_node(node);
+    }
+}
+
+/// Visit the body of `node` if it is a loop.
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+    if let Node::Loop(body, _, _) = node {
+        visitor.visit_block(body);
+    }
+}
+
+/// Visitor over the syntax tree that can modify the nodes. By default, it walks every node,
+/// descending into loop bodies.
+pub trait VisitorMut {
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block);
+    }
+
+    fn visit_node_mut(&mut self, node: &mut Node) {
+        walk_node_mut(self, node);
+    }
+}
+
+/// Visit every node of `block`, allowing the visitor to modify them.
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut Block) {
+    for node in &mut block.0 {
+        visitor.visit_node_mut(node);
+    }
+}
+
+/// Visit the body of `node` if it is a loop, allowing the visitor to modify it.
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut Node) {
+    if let Node::Loop(body, _, _) = node {
+        visitor.visit_block_mut(body);
+    }
+}
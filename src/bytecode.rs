@@ -0,0 +1,357 @@
+//! Binary format for compiled programs, so they can be loaded without parsing the source again.
+//! The command stream is stored optimized: a run of the same `>`, `<`, `+` or `-` is stored as a
+//! single op with a count, and expanded back into commands when loading.
+//!
+//! All integers are little-endian.
+//!
+//! | Field        | Size          | Description                                                  |
+//! | ------------ | ------------- | ------------------------------------------------------------ |
+//! | magic        | 4             | `BFC\0`                                                      |
+//! | version      | 2             | Format version, currently 1                                  |
+//! | flags        | 1             | bit 0: `dynamic_size`, bit 1: `wrapping`                     |
+//! | eof_behavior | 1             | 0: as is, 1: negative one, 2: zero                           |
+//! | newline_mode | 1             | 0: CRLF, 1: LF                                               |
+//! | input_mode   | 1             | 0: ascii, 1: digit                                           |
+//! | array_size   | 8             |                                                              |
+//! | count        | 4             | Number of ops, including the final `Halt`                    |
+//! | ops          | variable      | Opcode (1 byte), followed by the run length (4 bytes) for `>`, `<`, `+` and `-`, or the index of the matching op (4 bytes) for `[` and `]` |
+//! | source map   | 12 * commands | Character index, line and column (4 bytes each) of each command, after expanding runs |
+//! | checksum     | 4             | FNV-1a hash of everything before                             |
+
+use std::convert::TryFrom;
+
+use crate::error::*;
+use crate::location::*;
+use crate::program::*;
+use crate::settings::*;
+
+/// Magic number at the start of every bytecode file
+pub const MAGIC: &[u8; 4] = b"BFC\0";
+
+/// Current version of the format
+pub const VERSION: u16 = 1;
+
+/// Check whether `bytes` looks like bytecode rather than source code.
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Serialize `program`, together with the settings it is meant to run with. Fails if the program,
+/// or the source it was compiled from, is too large for the 32-bit fields of the format.
+pub fn encode(program: &Program, settings: &Settings) -> Result<Vec<u8>, BytecodeError> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+
+    let mut flags = 0;
+    if settings.dynamic_size {
+        flags |= 1;
+    }
+    if settings.wrapping {
+        flags |= 2;
+    }
+    bytes.push(flags);
+    bytes.push(match settings.eof_behavior {
+        EofBehavior::AsIs => 0,
+        EofBehavior::NegativeOne => 1,
+        EofBehavior::Zero => 2,
+    });
+    bytes.push(match settings.newline_mode {
+        NewlineMode::CRLF => 0,
+        NewlineMode::LF => 1,
+    });
+    bytes.push(match settings.input_mode {
+        InputMode::Ascii => 0,
+        InputMode::Digit => 1,
+    });
+    bytes.extend_from_slice(&(settings.array_size as u64).to_le_bytes());
+
+    let ops = ops(&program.commands);
+    bytes.extend_from_slice(&u32_of(ops.len())?.to_le_bytes());
+    for op in &ops {
+        let (opcode, arg) = match *op {
+            Op::Halt => (0, None),
+            Op::Right(count) => (1, Some(count)),
+            Op::Left(count) => (2, Some(count)),
+            Op::Inc(count) => (3, Some(count)),
+            Op::Dec(count) => (4, Some(count)),
+            Op::Open(dst) => (5, Some(dst)),
+            Op::Close(dst) => (6, Some(dst)),
+            Op::Read => (7, None),
+            Op::Write => (8, None),
+        };
+        bytes.push(opcode);
+        if let Some(arg) = arg {
+            bytes.extend_from_slice(&u32_of(arg)?.to_le_bytes());
+        }
+    }
+
+    for loc in &program.locations {
+        bytes.extend_from_slice(&u32_of(loc.index)?.to_le_bytes());
+        bytes.extend_from_slice(&u32_of(loc.line)?.to_le_bytes());
+        bytes.extend_from_slice(&u32_of(loc.column)?.to_le_bytes());
+    }
+
+    let checksum = fnv1a(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    Ok(bytes)
+}
+
+/// `val` as a 32-bit field
+fn u32_of(val: usize) -> Result<u32, BytecodeError> {
+    u32::try_from(val).map_err(|_| BytecodeError::TooLarge)
+}
+
+/// Deserialize a program and the settings it is meant to run with. The checksum, the ops and
+/// their jump targets are all verified.
+pub fn decode(bytes: &[u8]) -> Result<(Program, Settings), BytecodeError> {
+    if !is_bytecode(bytes) {
+        return Err(BytecodeError::InvalidMagic);
+    }
+    if bytes.len() < MAGIC.len() + 6 {
+        return Err(BytecodeError::Truncated);
+    }
+
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(BytecodeError::UnsupportedVersion(version));
+    }
+
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    if fnv1a(body) != u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) {
+        return Err(BytecodeError::ChecksumMismatch);
+    }
+
+    let mut reader = Reader {
+        bytes: body,
+        pos: MAGIC.len() + 2,
+    };
+
+    let flags = reader.u8()?;
+    let eof_behavior = match reader.u8()? {
+        0 => EofBehavior::AsIs,
+        1 => EofBehavior::NegativeOne,
+        2 => EofBehavior::Zero,
+        _ => return Err(BytecodeError::InvalidSettings),
+    };
+    let newline_mode = match reader.u8()? {
+        0 => NewlineMode::CRLF,
+        1 => NewlineMode::LF,
+        _ => return Err(BytecodeError::InvalidSettings),
+    };
+    let input_mode = match reader.u8()? {
+        0 => InputMode::Ascii,
+        1 => InputMode::Digit,
+        _ => return Err(BytecodeError::InvalidSettings),
+    };
+    let array_size = reader.u64()? as usize;
+    let settings = Settings {
+        dynamic_size: flags & 1 != 0,
+        array_size,
+        eof_behavior,
+        newline_mode,
+        ignore_newline: false,
+        input_mode,
+        wrapping: flags & 2 != 0,
+    };
+
+    let count = reader.u32()? as usize;
+    let mut ops = Vec::new();
+    for _ in 0..count {
+        ops.push(match reader.u8()? {
+            0 => Op::Halt,
+            1 => Op::Right(reader.u32()? as usize),
+            2 => Op::Left(reader.u32()? as usize),
+            3 => Op::Inc(reader.u32()? as usize),
+            4 => Op::Dec(reader.u32()? as usize),
+            5 => Op::Open(reader.u32()? as usize),
+            6 => Op::Close(reader.u32()? as usize),
+            7 => Op::Read,
+            8 => Op::Write,
+            _ => return Err(BytecodeError::InvalidCommand(ops.len())),
+        });
+    }
+    verify(&ops)?;
+
+    // the source map has an entry for every command once runs are expanded
+    let len = ops.iter().map(Op::len).sum::<usize>();
+    match len.checked_mul(12) {
+        Some(size) if size == body.len() - reader.pos => {}
+        Some(size) if size < body.len() - reader.pos => return Err(BytecodeError::TrailingData),
+        _ => return Err(BytecodeError::Truncated),
+    }
+
+    let mut locations = Vec::new();
+    for _ in 0..len {
+        locations.push(Location {
+            index: reader.u32()? as usize,
+            line: reader.u32()? as usize,
+            column: reader.u32()? as usize,
+        });
+    }
+
+    Ok((
+        Program {
+            commands: expand(&ops),
+            locations,
+        },
+        settings,
+    ))
+}
+
+/// Command as stored in bytecode, with runs of the same command contracted into one
+#[derive(Clone, Copy)]
+enum Op {
+    Halt,
+    Right(usize),
+    Left(usize),
+    Inc(usize),
+    Dec(usize),
+    /// Index of the matching `Close` op
+    Open(usize),
+    /// Index of the matching `Open` op
+    Close(usize),
+    Read,
+    Write,
+}
+
+impl Op {
+    /// Number of commands the op stands for
+    fn len(&self) -> usize {
+        match *self {
+            Op::Right(count) | Op::Left(count) | Op::Inc(count) | Op::Dec(count) => count,
+            _ => 1,
+        }
+    }
+}
+
+/// Contract runs of `commands` into ops
+fn ops(commands: &[Command]) -> Vec<Op> {
+    let mut ops: Vec<Op> = Vec::new();
+    // index of the op each command is part of
+    let mut op_of = Vec::new();
+    for command in commands {
+        let op = match (*command, ops.last_mut()) {
+            (Command::Right, Some(Op::Right(count)))
+            | (Command::Left, Some(Op::Left(count)))
+            | (Command::Inc, Some(Op::Inc(count)))
+            | (Command::Dec, Some(Op::Dec(count))) => {
+                *count += 1;
+                None
+            }
+            (Command::Halt, _) => Some(Op::Halt),
+            (Command::Right, _) => Some(Op::Right(1)),
+            (Command::Left, _) => Some(Op::Left(1)),
+            (Command::Inc, _) => Some(Op::Inc(1)),
+            (Command::Dec, _) => Some(Op::Dec(1)),
+            // the target is set when reaching the matching close
+            (Command::Open(_), _) => Some(Op::Open(0)),
+            (Command::Close(open), _) => {
+                let open = op_of[open];
+                ops[open] = Op::Open(ops.len());
+                Some(Op::Close(open))
+            }
+            (Command::Read, _) => Some(Op::Read),
+            (Command::Write, _) => Some(Op::Write),
+        };
+        ops.extend(op);
+        op_of.push(ops.len() - 1);
+    }
+    ops
+}
+
+/// Expand verified `ops` back into commands
+fn expand(ops: &[Op]) -> Vec<Command> {
+    // index of the first command of each op
+    let starts: Vec<usize> = ops
+        .iter()
+        .scan(0, |start, op| {
+            let first = *start;
+            *start += op.len();
+            Some(first)
+        })
+        .collect();
+
+    let mut commands = Vec::new();
+    for op in ops {
+        let (command, count) = match *op {
+            Op::Halt => (Command::Halt, 1),
+            Op::Right(count) => (Command::Right, count),
+            Op::Left(count) => (Command::Left, count),
+            Op::Inc(count) => (Command::Inc, count),
+            Op::Dec(count) => (Command::Dec, count),
+            Op::Open(dst) => (Command::Open(starts[dst]), 1),
+            Op::Close(dst) => (Command::Close(starts[dst]), 1),
+            Op::Read => (Command::Read, 1),
+            Op::Write => (Command::Write, 1),
+        };
+        commands.extend(std::iter::repeat_n(command, count));
+    }
+    commands
+}
+
+/// Check that every jump lands on its matching bracket, that runs are not empty, and that the
+/// program ends with its only `Halt`.
+fn verify(ops: &[Op]) -> Result<(), BytecodeError> {
+    for (i, op) in ops.iter().enumerate() {
+        match *op {
+            Op::Open(dst) => match ops.get(dst) {
+                Some(Op::Close(src)) if *src == i && dst > i => {}
+                _ => return Err(BytecodeError::InvalidJump(i)),
+            },
+            Op::Close(dst) => match ops.get(dst) {
+                Some(Op::Open(src)) if *src == i && dst < i => {}
+                _ => return Err(BytecodeError::InvalidJump(i)),
+            },
+            // the interpreter stops at the first halt, the syntax tree at the end
+            Op::Halt if i + 1 < ops.len() => return Err(BytecodeError::InvalidCommand(i)),
+            _ if op.len() == 0 => return Err(BytecodeError::InvalidCommand(i)),
+            _ => {}
+        }
+    }
+
+    match ops.last() {
+        Some(Op::Halt) => Ok(()),
+        _ => Err(BytecodeError::MissingHalt),
+    }
+}
+
+/// 32-bit FNV-1a hash
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Cursor over the bytes being decoded
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], BytecodeError> {
+        if self.pos + len > self.bytes.len() {
+            return Err(BytecodeError::Truncated);
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, BytecodeError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
@@ -7,6 +7,16 @@ impl Cells {
         Self(vec![0; size])
     }
 
+    /// Create cells preloaded with `tape`. The rest of the array is filled with zeros. If `tape`
+    /// is longer than `size`, the array is extended to fit it.
+    pub fn with_tape(tape: &[u8], size: usize) -> Self {
+        let mut cells = tape.to_vec();
+        if cells.len() < size {
+            cells.resize(size, 0);
+        }
+        Self(cells)
+    }
+
     pub fn get_mut(&mut self, index: usize) -> Option<&mut u8> {
         if index < self.0.len() {
             Some(&mut self.0[index])
@@ -15,12 +25,16 @@ impl Cells {
         }
     }
 
+    /// Like `get_mut`, but extend the array to fit `index`. Indices left of the first cell, which
+    /// wrapped around to the end of `usize`, are still out of bound.
     pub fn get_mut_expand(&mut self, index: usize) -> Option<&mut u8> {
         if index < self.0.len() {
             Some(&mut self.0[index])
-        } else {
+        } else if index <= isize::MAX as usize {
             self.0.resize(index + 1, 0);
             Some(&mut self.0[index])
+        } else {
+            None
         }
     }
 }
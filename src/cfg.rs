@@ -0,0 +1,208 @@
+//! Control-flow graph of a program. Basic blocks are straight-line runs of commands, each ending
+//! with the '[' or ']' that branches out of it, or with the end of the program.
+
+use std::fmt;
+use std::fmt::Write;
+
+use crate::location::*;
+use crate::program::*;
+
+/// Maximum number of commands per line of a block label in DOT output
+const LABEL_WIDTH: usize = 40;
+
+/// Why control goes from one block to another
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// '[' on a non-zero cell, entering the loop body
+    Enter,
+    /// '[' on a zero cell, skipping the loop
+    Skip,
+    /// ']' on a non-zero cell, running the loop body again. This is the only back edge.
+    Repeat,
+    /// ']' on a zero cell, leaving the loop
+    Exit,
+}
+
+/// Edge between basic blocks
+#[derive(Clone, Debug)]
+pub struct Edge {
+    /// Index of the target block
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// Straight-line run of commands
+#[derive(Clone, Debug)]
+pub struct BasicBlock {
+    /// Index of the first command
+    pub start: usize,
+    /// Index of the last command, the bracket or `Halt` ending the block
+    pub end: usize,
+    pub edges: Vec<Edge>,
+}
+
+/// Control-flow graph
+pub struct Cfg {
+    program: Program,
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl Cfg {
+    pub fn new(program: &Program) -> Self {
+        // every block starts at the beginning or right after a bracket
+        let mut starts = vec![0];
+        let mut block_of = vec![0; program.commands.len() + 1];
+        for (i, command) in program.commands.iter().enumerate() {
+            block_of[i] = starts.len() - 1;
+            if matches!(command, Command::Open(_) | Command::Close(_)) {
+                starts.push(i + 1);
+            }
+        }
+
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(i, start)| {
+                let end = starts
+                    .get(i + 1)
+                    .map_or(program.commands.len(), |next| *next)
+                    - 1;
+                let edge = |to: usize, kind| Edge {
+                    to: block_of[to],
+                    kind,
+                };
+                let edges = match program.commands[end] {
+                    Command::Open(close) => {
+                        vec![
+                            edge(end + 1, EdgeKind::Enter),
+                            edge(close + 1, EdgeKind::Skip),
+                        ]
+                    }
+                    Command::Close(open) => {
+                        vec![
+                            edge(open + 1, EdgeKind::Repeat),
+                            edge(end + 1, EdgeKind::Exit),
+                        ]
+                    }
+                    _ => Vec::new(),
+                };
+
+                BasicBlock {
+                    start: *start,
+                    end,
+                    edges,
+                }
+            })
+            .collect();
+
+        Self {
+            program: program.clone(),
+            blocks,
+        }
+    }
+
+    /// Graph in Graphviz DOT format. Back edges are dashed and labeled with the source span of
+    /// their loop.
+    pub fn dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph cfg {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            let code = self.code(block);
+            let mut lines: Vec<String> = code
+                .chars()
+                .collect::<Vec<char>>()
+                .chunks(LABEL_WIDTH)
+                .map(|chunk| chunk.iter().collect())
+                .collect();
+            lines.insert(0, self.span(block.start, block.end));
+            writeln!(dot, "    b{} [label=\"{}\"];", i, lines.join("\\l") + "\\l").unwrap();
+        }
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            for edge in &block.edges {
+                let attributes = match edge.kind {
+                    EdgeKind::Enter => String::from("label=\"enter\""),
+                    EdgeKind::Skip => String::from("label=\"skip\""),
+                    EdgeKind::Exit => String::from("label=\"exit\""),
+                    EdgeKind::Repeat => {
+                        let open = match self.program.commands[block.end] {
+                            Command::Close(open) => open,
+                            _ => unreachable!("back edges start at ']'"),
+                        };
+                        format!(
+                            "label=\"loop {}\", style=dashed",
+                            self.span(open, block.end)
+                        )
+                    }
+                };
+                writeln!(dot, "    b{} -> b{} [{}];", i, edge.to, attributes).unwrap();
+            }
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    /// Commands of `block`, with `Halt` written as "halt"
+    fn code(&self, block: &BasicBlock) -> String {
+        let code: String = self.program.commands[block.start..=block.end]
+            .iter()
+            .map(|command| command.to_string())
+            .collect();
+        match self.program.commands[block.end] {
+            Command::Halt => code + "halt",
+            _ => code,
+        }
+    }
+
+    /// Source span from the command at `start` to the one at `end`
+    fn span(&self, start: usize, end: usize) -> String {
+        span(self.program.locations[start], self.program.locations[end])
+    }
+}
+
+fn span(start: Location, end: Location) -> String {
+    if start == end {
+        start.to_string()
+    } else {
+        format!("{}-{}", start, end)
+    }
+}
+
+impl fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EdgeKind::Enter => write!(f, "enter"),
+            EdgeKind::Skip => write!(f, "skip"),
+            EdgeKind::Repeat => write!(f, "repeat"),
+            EdgeKind::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+impl fmt::Display for Cfg {
+    /// One line per block: its index, source span, commands and edges
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, block) in self.blocks.iter().enumerate() {
+            write!(
+                f,
+                "b{} {} {}",
+                i,
+                self.span(block.start, block.end),
+                self.code(block)
+            )?;
+            let edges: Vec<String> = block
+                .edges
+                .iter()
+                .map(|edge| format!("b{} ({})", edge.to, edge.kind))
+                .collect();
+            if !edges.is_empty() {
+                write!(f, " -> {}", edges.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -1,49 +1,160 @@
 use crate::error::*;
+use crate::location::*;
 use crate::program::*;
 
 /// Compiler
 pub struct Compiler {
     commands: Vec<Command>,
-    opens: Vec<usize>,
+    locations: Vec<Location>,
+    opens: Vec<(usize, Location)>,
+    errors: Vec<CompileError>,
+    comment: Option<char>,
 }
 
 impl Compiler {
     pub fn new() -> Compiler {
         Compiler {
             commands: Vec::new(),
+            locations: Vec::new(),
             opens: Vec::new(),
+            errors: Vec::new(),
+            comment: None,
         }
     }
 
-    pub fn compile(mut self, src: String) -> Result<Program, CompileError> {
+    /// Enable strict syntax. Only commands, whitespace and line comments starting with `comment`
+    /// are allowed, every other character is a syntax error.
+    pub fn strict(mut self, comment: char) -> Compiler {
+        self.comment = Some(comment);
+        self
+    }
+
+    /// Compile `src` into a program. Every error found is reported, not only the first one.
+    pub fn compile(mut self, src: String) -> Result<Program, Vec<CompileError>> {
+        let mut in_comment = false;
+        let mut loc = Location {
+            index: 0,
+            line: 1,
+            column: 1,
+        };
+
         for (i, ch) in src.chars().enumerate() {
-            match ch {
-                '>' => self.commands.push(Command::Right),
-                '<' => self.commands.push(Command::Left),
-                '+' => self.commands.push(Command::Inc),
-                '-' => self.commands.push(Command::Dec),
-                '[' => {
-                    self.opens.push(self.commands.len());
-                    self.commands.push(Command::Open(0));
-                }
-                ']' => match self.opens.pop() {
-                    Some(val) => {
-                        self.commands[val] = Command::Open(self.commands.len());
-                        self.commands.push(Command::Close(val));
+            loc.index = i;
+
+            let len = self.commands.len();
+            if in_comment {
+                in_comment = ch != '\n';
+            } else {
+                match ch {
+                    '>' => self.commands.push(Command::Right),
+                    '<' => self.commands.push(Command::Left),
+                    '+' => self.commands.push(Command::Inc),
+                    '-' => self.commands.push(Command::Dec),
+                    '[' => {
+                        self.opens.push((self.commands.len(), loc));
+                        self.commands.push(Command::Open(0));
                     }
-                    None => return Err(CompileError::UnmatchedBracket(i)),
-                },
-                ',' => self.commands.push(Command::Read),
-                '.' => self.commands.push(Command::Write),
-                _ => {}
+                    ']' => match self.opens.pop() {
+                        Some((val, _)) => {
+                            self.commands[val] = Command::Open(self.commands.len());
+                            self.commands.push(Command::Close(val));
+                        }
+                        None => self.errors.push(CompileError::UnmatchedClose(loc)),
+                    },
+                    ',' => self.commands.push(Command::Read),
+                    '.' => self.commands.push(Command::Write),
+                    _ => match self.comment {
+                        Some(comment) if ch == comment => in_comment = true,
+                        Some(_) if !ch.is_whitespace() => {
+                            self.errors.push(CompileError::Syntax(loc, ch))
+                        }
+                        _ => {}
+                    },
+                }
+            }
+
+            if self.commands.len() > len {
+                self.locations.push(loc);
+            }
+
+            if ch == '\n' {
+                loc.line += 1;
+                loc.column = 1;
+            } else {
+                loc.column += 1;
             }
         }
 
-        if self.opens.len() > 0 {
-            return Err(CompileError::UnmatchedBracket(self.opens[0]));
+        for (_, open) in std::mem::take(&mut self.opens) {
+            let end = loop_end(&src, open);
+            self.errors.push(CompileError::UnmatchedOpen(open, end));
         }
 
+        if !self.errors.is_empty() {
+            self.errors.sort_by_key(|err| match err {
+                CompileError::Syntax(loc, _) => *loc,
+                CompileError::UnmatchedOpen(loc, _) => *loc,
+                CompileError::UnmatchedClose(loc) => *loc,
+            });
+            return Err(self.errors);
+        }
+
+        loc.index = src.chars().count();
         self.commands.push(Command::Halt);
-        Ok(Program(self.commands))
+        self.locations.push(loc);
+        Ok(Program {
+            commands: self.commands,
+            locations: self.locations,
+        })
     }
 }
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Guess where the loop body opened at `open` ends, judging by indentation. The body is assumed
+/// to be every following line indented deeper than the line of `open`, so it ends at the last
+/// non-whitespace character before the first line that is not.
+fn loop_end(src: &str, open: Location) -> Location {
+    let indent = |line: &str| line.chars().take_while(|ch| ch.is_whitespace()).count();
+
+    let mut lines = src.split('\n');
+    let mut index = 0;
+    for line in lines.by_ref().take(open.line - 1) {
+        index += line.chars().count() + 1;
+    }
+
+    let first = lines.next().unwrap_or_default();
+    let open_indent = indent(first);
+    let mut end = last_char(first, open.line, index).unwrap_or(open);
+    index += first.chars().count() + 1;
+
+    for (line, text) in lines.enumerate().map(|(i, text)| (open.line + 1 + i, text)) {
+        if let Some(last) = last_char(text, line, index) {
+            if indent(text) <= open_indent {
+                break;
+            }
+            end = last;
+        }
+        index += text.chars().count() + 1;
+    }
+
+    end
+}
+
+/// Location of the last non-whitespace character of `text`, which starts at character `index`
+/// on `line`
+fn last_char(text: &str, line: usize, index: usize) -> Option<Location> {
+    text.chars()
+        .enumerate()
+        .filter(|(_, ch)| !ch.is_whitespace())
+        .last()
+        .map(|(column, _)| Location {
+            index: index + column,
+            line,
+            column: column + 1,
+        })
+}
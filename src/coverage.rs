@@ -0,0 +1,266 @@
+//! Code coverage, tracking which commands and loop bodies ran. Coverage can be collected over
+//! several runs by observing all of them with the same `Coverage`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::location::*;
+use crate::observer::*;
+use crate::preprocessor::*;
+use crate::program::*;
+
+/// Default number of commands each run of `brainrust coverage` runs before it is stopped
+pub const COVERAGE_STEPS: u64 = 10_000_000;
+
+/// Observer tracking how many times each command ran, and which way each loop went
+pub struct Coverage {
+    program: Program,
+    /// Number of times each command ran
+    pub hits: Vec<u64>,
+    /// For each '[', number of times the loop body was entered
+    pub entered: Vec<u64>,
+    /// For each '[', number of times the loop body was skipped
+    pub skipped: Vec<u64>,
+}
+
+impl Coverage {
+    pub fn new(program: &Program) -> Self {
+        let len = program.commands.len();
+        Self {
+            program: program.clone(),
+            hits: vec![0; len],
+            entered: vec![0; len],
+            skipped: vec![0; len],
+        }
+    }
+
+    /// Add the coverage of `other`, which must be collected on the same program.
+    pub fn merge(&mut self, other: &Coverage) {
+        let counts = [
+            (&mut self.hits, &other.hits),
+            (&mut self.entered, &other.entered),
+            (&mut self.skipped, &other.skipped),
+        ];
+        for (counts, other) in counts {
+            for (count, other) in counts.iter_mut().zip(other) {
+                *count += other;
+            }
+        }
+    }
+
+    /// Number of commands that ran at least once, and number of commands, excluding `Halt`
+    pub fn commands(&self) -> (usize, usize) {
+        let commands = self.program.commands.len() - 1;
+        let hit = self.hits[..commands]
+            .iter()
+            .filter(|hits| **hits > 0)
+            .count();
+        (hit, commands)
+    }
+
+    /// Number of loop bodies that ran at least once, and number of loops
+    pub fn loops(&self) -> (usize, usize) {
+        let opens: Vec<usize> = self.opens().collect();
+        let hit = opens.iter().filter(|open| self.entered[**open] > 0).count();
+        (hit, opens.len())
+    }
+
+    /// Summary of command and loop coverage
+    pub fn summary(&self) -> String {
+        let (commands_hit, commands) = self.commands();
+        let (loops_hit, loops) = self.loops();
+        format!(
+            "Commands: {}/{} ({:.2}%)\nLoop bodies: {}/{} ({:.2}%)\n",
+            commands_hit,
+            commands,
+            percent(commands_hit, commands),
+            loops_hit,
+            loops,
+            percent(loops_hit, loops)
+        )
+    }
+
+    /// Coverage in lcov tracefile format. Lines are reported with the number of times their
+    /// commands ran, and every loop is a branch with two ways: entering the body or skipping it.
+    pub fn lcov(&self, source: &str) -> String {
+        self.lcov_by(&[source.to_string()], |loc| (source.to_string(), loc.line))
+    }
+
+    /// Coverage in lcov tracefile format, like `lcov`, for a preprocessed source. Lines are
+    /// mapped back through `map`, with one record per original file.
+    pub fn lcov_mapped(&self, map: &SourceMap) -> String {
+        let files: Vec<String> = map
+            .files
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        self.lcov_by(&files, |loc| match map.lookup(loc) {
+            Some((path, loc)) => (path.display().to_string(), loc.line),
+            None => (String::new(), loc.line),
+        })
+    }
+
+    /// Coverage in lcov tracefile format, with a record for each of `files` and the file and
+    /// line of each location given by `locate`
+    fn lcov_by(&self, files: &[String], locate: impl Fn(Location) -> (String, usize)) -> String {
+        let mut files: BTreeMap<String, LcovFile> = files
+            .iter()
+            .map(|file| (file.clone(), LcovFile::default()))
+            .collect();
+        for open in self.opens() {
+            let (file, line) = locate(self.program.locations[open]);
+            let branches = &mut files.entry(file).or_default().branches;
+            branches.push((line, self.entered[open], self.skipped[open]));
+        }
+        let commands = self.program.commands.len() - 1;
+        for (hits, loc) in self.hits[..commands]
+            .iter()
+            .zip(&self.program.locations[..commands])
+        {
+            let (file, line) = locate(*loc);
+            let line = files
+                .entry(file)
+                .or_default()
+                .lines
+                .entry(line)
+                .or_insert(0);
+            *line = (*line).max(*hits);
+        }
+
+        let mut lcov = String::new();
+        for (file, LcovFile { branches, lines }) in &files {
+            writeln!(lcov, "TN:").unwrap();
+            writeln!(lcov, "SF:{}", file).unwrap();
+
+            let mut branches_hit = 0;
+            for (block, (line, entered, skipped)) in branches.iter().enumerate() {
+                for (branch, taken) in [entered, skipped].iter().enumerate() {
+                    writeln!(lcov, "BRDA:{},{},{},{}", line, block, branch, taken).unwrap();
+                    if **taken > 0 {
+                        branches_hit += 1;
+                    }
+                }
+            }
+            writeln!(lcov, "BRF:{}", branches.len() * 2).unwrap();
+            writeln!(lcov, "BRH:{}", branches_hit).unwrap();
+
+            for (line, hits) in lines {
+                writeln!(lcov, "DA:{},{}", line, hits).unwrap();
+            }
+            writeln!(lcov, "LF:{}", lines.len()).unwrap();
+            let lines_hit = lines.values().filter(|hits| **hits > 0).count();
+            writeln!(lcov, "LH:{}", lines_hit).unwrap();
+            writeln!(lcov, "end_of_record").unwrap();
+        }
+
+        lcov
+    }
+
+    /// Source code annotated with coverage. Each line is prefixed with the number of times its
+    /// commands ran, or `#####` if none of them ran. Lines where only some commands ran are
+    /// followed by a line marking the commands that never ran with `^`.
+    pub fn annotate(&self, src: &str) -> String {
+        let lines = self.lines();
+
+        let mut missed: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, loc) in self.program.locations[..self.program.commands.len() - 1]
+            .iter()
+            .enumerate()
+        {
+            if self.hits[i] == 0 {
+                missed.entry(loc.line).or_default().push(loc.column);
+            }
+        }
+
+        let mut listing = String::new();
+        for (i, text) in src.split('\n').enumerate() {
+            let line = i + 1;
+            let text = text.trim_end_matches('\r');
+            match lines.get(&line) {
+                None => writeln!(listing, "{:>10} | {}", "", text).unwrap(),
+                Some((0, _)) => writeln!(listing, "{:>10} | {}", "#####", text).unwrap(),
+                Some((hits, partial)) => {
+                    writeln!(listing, "{:>10} | {}", hits, text).unwrap();
+                    if *partial {
+                        let mut marks = String::new();
+                        for column in &missed[&line] {
+                            while marks.chars().count() < column - 1 {
+                                marks.push(' ');
+                            }
+                            marks.push('^');
+                        }
+                        writeln!(listing, "{:>10} | {}", "", marks).unwrap();
+                    }
+                }
+            }
+        }
+
+        listing
+    }
+
+    /// Index of every '['
+    fn opens(&self) -> impl Iterator<Item = usize> + '_ {
+        self.program
+            .commands
+            .iter()
+            .enumerate()
+            .filter_map(|(i, command)| match command {
+                Command::Open(_) => Some(i),
+                _ => None,
+            })
+    }
+
+    /// For each line with commands: the highest number of times one of its commands ran, and
+    /// whether some of its commands never ran while others did
+    fn lines(&self) -> BTreeMap<usize, (u64, bool)> {
+        let mut lines: BTreeMap<usize, (u64, bool, bool)> = BTreeMap::new();
+        let commands = self.program.commands.len() - 1;
+        for (hits, loc) in self.hits[..commands]
+            .iter()
+            .zip(&self.program.locations[..commands])
+        {
+            let line = lines.entry(loc.line).or_insert((0, false, false));
+            line.0 = line.0.max(*hits);
+            if *hits > 0 {
+                line.1 = true;
+            } else {
+                line.2 = true;
+            }
+        }
+
+        lines
+            .into_iter()
+            .map(|(line, (hits, some_hit, some_missed))| (line, (hits, some_hit && some_missed)))
+            .collect()
+    }
+}
+
+/// Coverage of one file in an lcov report
+#[derive(Default)]
+struct LcovFile {
+    /// Line of each loop, with the number of times its body was entered and skipped
+    branches: Vec<(usize, u64, u64)>,
+    /// Highest number of times a command ran on each line
+    lines: BTreeMap<usize, u64>,
+}
+
+impl Observer for Coverage {
+    fn observe(&mut self, step: &Step) {
+        self.hits[step.command_ptr] += 1;
+        if let Command::Open(_) = step.command {
+            if step.before != 0 {
+                self.entered[step.command_ptr] += 1;
+            } else {
+                self.skipped[step.command_ptr] += 1;
+            }
+        }
+    }
+}
+
+fn percent(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
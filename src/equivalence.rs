@@ -0,0 +1,243 @@
+//! Bounded equivalence checking. Two programs are run on the same inputs, every input up to a
+//! length and then random ones, and compared on their output, final tape and how they stopped.
+//!
+//! Inputs are only extended when one of the programs read past their end: if neither did, adding
+//! more bytes can't change anything. Programs reading little input are checked quickly.
+
+use std::cell::{Cell, RefCell};
+use std::io::Read;
+use std::rc::Rc;
+
+use crate::error::*;
+use crate::interpreter_builder::*;
+use crate::program::*;
+use crate::settings::*;
+
+/// Options of the equivalence check
+#[derive(Clone, Debug)]
+pub struct EquivOptions {
+    /// Every input up to this length is checked
+    pub max_length: usize,
+    /// Bytes inputs are made of
+    pub alphabet: Vec<u8>,
+    /// Number of random inputs checked after the exhaustive ones
+    pub random: usize,
+    /// Maximum length of random inputs
+    pub random_length: usize,
+    pub seed: u64,
+    /// Number of commands run on a single input before giving up
+    pub max_steps: u64,
+    /// Compare the final tapes of the programs, not only their outputs
+    pub compare_tape: bool,
+}
+
+impl Default for EquivOptions {
+    fn default() -> Self {
+        Self {
+            max_length: 2,
+            alphabet: (0..=255).collect(),
+            random: 0,
+            random_length: 64,
+            seed: 1,
+            max_steps: 1_000_000,
+            compare_tape: true,
+        }
+    }
+}
+
+/// How a program stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ending {
+    Halted,
+    IndexOutOfBound,
+    ReadFailed,
+    WriteFailed,
+    ParseNumError,
+    InfiniteLoop,
+    /// Ran `max_steps` commands without stopping
+    StepLimit,
+}
+
+impl From<RunResult> for Ending {
+    fn from(result: RunResult) -> Self {
+        match result {
+            RunResult::None => Ending::StepLimit,
+            RunResult::Halted => Ending::Halted,
+            RunResult::IndexOutOfBound(_) => Ending::IndexOutOfBound,
+            RunResult::ReadFailed => Ending::ReadFailed,
+            RunResult::WriteFailed => Ending::WriteFailed,
+            RunResult::ParseNumError => Ending::ParseNumError,
+            RunResult::InfiniteLoop { .. } => Ending::InfiniteLoop,
+        }
+    }
+}
+
+/// What a program did on an input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub output: Vec<u8>,
+    /// Final tape, without trailing zeros
+    pub tape: Vec<u8>,
+    pub ending: Ending,
+}
+
+/// Input the programs behave differently on
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub input: Vec<u8>,
+    pub a: Outcome,
+    pub b: Outcome,
+}
+
+/// Result of the equivalence check
+#[derive(Clone, Debug)]
+pub struct Equivalence {
+    /// Number of inputs the programs were run on
+    pub checked: usize,
+    /// Number of inputs both programs ran out of steps on, which tell nothing
+    pub inconclusive: usize,
+    /// First input the programs behave differently on
+    pub mismatch: Option<Mismatch>,
+}
+
+/// Run program `a` and `b` on the inputs given by `options`, until they behave differently on one.
+pub fn check_equivalence(
+    a: &Program,
+    b: &Program,
+    settings: &Settings,
+    options: &EquivOptions,
+) -> Equivalence {
+    let mut result = Equivalence {
+        checked: 0,
+        inconclusive: 0,
+        mismatch: None,
+    };
+
+    // return whether the programs behave the same, and whether either read past the input
+    let mut check = |input: Vec<u8>| {
+        let (outcome_a, eof_a) = run(a, settings, &input, options);
+        let (outcome_b, eof_b) = run(b, settings, &input, options);
+        result.checked += 1;
+        if outcome_a.ending == Ending::StepLimit && outcome_b.ending == Ending::StepLimit {
+            result.inconclusive += 1;
+            return (true, true);
+        }
+
+        let same = outcome_a.output == outcome_b.output
+            && outcome_a.ending == outcome_b.ending
+            && (!options.compare_tape || outcome_a.tape == outcome_b.tape);
+        if !same {
+            result.mismatch = Some(Mismatch {
+                input,
+                a: outcome_a,
+                b: outcome_b,
+            });
+        }
+        (same, eof_a || eof_b)
+    };
+
+    // inputs in order of length, extending those the programs read to the end of
+    let mut differ = false;
+    let mut inputs = vec![Vec::new()];
+    'lengths: for length in 0..=options.max_length {
+        let mut extended = Vec::new();
+        for input in inputs {
+            let (same, eof) = check(input.clone());
+            if !same {
+                differ = true;
+                break 'lengths;
+            }
+            if eof && length < options.max_length {
+                for byte in &options.alphabet {
+                    let mut longer = input.clone();
+                    longer.push(*byte);
+                    extended.push(longer);
+                }
+            }
+        }
+        inputs = extended;
+    }
+
+    let base = options.alphabet.len();
+    if !differ && base > 0 {
+        let mut rng = XorShift(options.seed.max(1));
+        for _ in 0..options.random {
+            let length = rng.below(options.random_length + 1);
+            let input = (0..length)
+                .map(|_| options.alphabet[rng.below(base)])
+                .collect();
+            if !check(input).0 {
+                break;
+            }
+        }
+    }
+
+    result
+}
+
+/// Run `program` on `input` for at most `options.max_steps` commands. Return what it did and
+/// whether it read past the end of the input.
+fn run(
+    program: &Program,
+    settings: &Settings,
+    input: &[u8],
+    options: &EquivOptions,
+) -> (Outcome, bool) {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = InterpreterBuilder::from_program(program.clone(), settings.clone())
+        .output(output.clone())
+        .build()
+        .unwrap();
+    let eof = Rc::new(Cell::new(false));
+    interpreter.reader = Box::new(Input {
+        bytes: input.to_vec(),
+        pos: 0,
+        eof: eof.clone(),
+    });
+
+    let mut state = interpreter.ready();
+    let ending = interpreter.run_for(&mut state, options.max_steps).into();
+
+    let mut tape = state.cells.0;
+    while tape.last() == Some(&0) {
+        tape.pop();
+    }
+    let output = output.borrow().clone();
+    let outcome = Outcome {
+        output,
+        tape,
+        ending,
+    };
+    (outcome, eof.get())
+}
+
+/// Input recording whether it was read past its end
+struct Input {
+    bytes: Vec<u8>,
+    pos: usize,
+    eof: Rc<Cell<bool>>,
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = (&self.bytes[self.pos..]).read(buf)?;
+        self.pos += read;
+        if read == 0 && !buf.is_empty() {
+            self.eof.set(true);
+        }
+        Ok(read)
+    }
+}
+
+/// Xorshift64* pseudorandom numbers, enough to pick inputs
+struct XorShift(u64);
+
+impl XorShift {
+    /// Number in `0..n`
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as usize % n
+    }
+}
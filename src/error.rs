@@ -1,15 +1,151 @@
+use std::path::PathBuf;
+
+use crate::location::*;
+
 /// Error during compilation
 #[derive(Debug)]
 pub enum CompileError {
     /// Syntactic error
     ///
-    /// - `usize` is the location of the error
+    /// - `Location` is the location of the error
     /// - `char` is the unknown character
-    Syntax(usize, char),
-    /// Not all brackets have a matching bracket
+    Syntax(Location, char),
+    /// '[' without a matching ']'
+    ///
+    /// - first `Location` is the location of the '['
+    /// - second `Location` is where its loop body probably ends, judging by indentation
+    UnmatchedOpen(Location, Location),
+    /// ']' without a matching '['
+    ///
+    /// - `Location` is the location of the ']'
+    UnmatchedClose(Location),
+}
+
+/// Error while compiling the high-level language (see `lang`)
+#[derive(Debug)]
+pub enum LangError {
+    /// Character that can't start a token
+    ///
+    /// - `Location` is the location of the character
+    /// - `char` is the character
+    InvalidChar(Location, char),
+    /// Number larger than 255
+    ///
+    /// - `Location` is the location of the number
+    /// - `String` is the number
+    InvalidNumber(Location, String),
+    /// String or character literal that is not closed, has an unknown escape, or has more than
+    /// one byte for a character
+    ///
+    /// - `Location` is the location of the opening quote
+    InvalidLiteral(Location),
+    /// Something else was expected
+    ///
+    /// - `Location` is the location of what was found instead
+    /// - `&str` is what was expected
+    Expected(Location, &'static str),
+    /// Variable used before being declared
+    ///
+    /// - `Location` is the location of the use
+    /// - `String` is the variable name
+    UndefinedVariable(Location, String),
+    /// Variable declared twice
+    ///
+    /// - `Location` is the location of the second declaration
+    /// - `String` is the variable name
+    RedefinedVariable(Location, String),
+}
+
+/// Error while preprocessing. Every location is in the file given with it.
+#[derive(Debug)]
+pub enum PreprocessError {
+    /// File could not be read
+    Io(PathBuf, std::io::Error),
+    /// `#define` without a name, or with invalid parameters
+    ///
+    /// - `Location` is the location of the directive
+    InvalidDefine(PathBuf, Location),
+    /// Macro defined twice
+    ///
+    /// - `Location` is the location of the second definition
+    /// - `String` is the macro name
+    Redefined(PathBuf, Location, String),
+    /// `#include` without a quoted file name
     ///
-    /// - `usize` is the location of the error
-    UnmatchedBracket(usize),
+    /// - `Location` is the location of the directive
+    InvalidInclude(PathBuf, Location),
+    /// Macro invoked with the wrong number of arguments, or without closing its arguments
+    ///
+    /// - `Location` is the location of the invocation
+    /// - `String` is the macro name
+    /// - `usize` is the number of parameters
+    ArgumentCount(PathBuf, Location, String, usize),
+    /// Macro expanding to itself
+    ///
+    /// - `Location` is the location of the invocation
+    /// - `String` is the macro name
+    RecursiveMacro(PathBuf, Location, String),
+    /// Repeat count given by a macro that does not expand to a number
+    ///
+    /// - `Location` is the location of the `{`
+    InvalidRepeat(PathBuf, Location),
+    /// Repeat expanding to more than `MAX_REPEAT` characters
+    ///
+    /// - `Location` is the location of the `{`
+    RepeatTooLarge(PathBuf, Location),
+    /// Macros and repeats expanding to more than `MAX_EXPANSION` characters in total
+    ///
+    /// - `Location` is the location of the macro invocation or `{` reaching the limit
+    ExpansionTooLarge(PathBuf, Location),
+}
+
+/// Error while encoding or decoding bytecode
+#[derive(Debug)]
+pub enum BytecodeError {
+    /// Missing magic number, the bytes are not bytecode
+    InvalidMagic,
+    /// Bytecode was written by an unsupported version of the format
+    ///
+    /// - `u16` is the version
+    UnsupportedVersion(u16),
+    /// Bytecode ended before all of its contents were read
+    Truncated,
+    /// Extra bytes after the contents
+    TrailingData,
+    /// Checksum does not match the contents, the bytecode is corrupted
+    ChecksumMismatch,
+    /// Unknown settings value
+    InvalidSettings,
+    /// Unknown opcode, empty run, or `Halt` before the last op
+    ///
+    /// - `usize` is the index of the op
+    InvalidCommand(usize),
+    /// Jump target is not the matching bracket
+    ///
+    /// - `usize` is the index of the op
+    InvalidJump(usize),
+    /// Program does not end with `Halt`
+    MissingHalt,
+    /// Program has a number of ops, a run, a jump target or a location too large for 32 bits
+    TooLarge,
+}
+
+/// Error while reading a test spec (see `spec`)
+#[derive(Debug)]
+pub enum SpecError {
+    /// Invalid TOML, or unknown or mistyped fields
+    ///
+    /// - `String` is the message of the TOML parser
+    Toml(String),
+    /// Unknown settings value
+    ///
+    /// - `&str` is the setting name
+    /// - `String` is the value
+    InvalidSetting(&'static str, String),
+    /// Unknown expected result
+    ///
+    /// - `String` is the result
+    InvalidResult(String),
 }
 
 /// Result of running
@@ -24,4 +160,11 @@ pub enum RunResult {
     ReadFailed,
     WriteFailed,
     ParseNumError,
+    /// The program repeated a state at a loop head without any I/O in between, so it will never
+    /// stop
+    ///
+    /// `location` is the '[' of the repeating loop
+    InfiniteLoop {
+        location: Location,
+    },
 }
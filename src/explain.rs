@@ -0,0 +1,128 @@
+//! Pseudocode of a program, lifted from its optimized form.
+//!
+//! Cells are written relative to the pointer as `c[offset]`. Pointer moves are tracked instead of
+//! being written, as long as every loop moves the pointer back to where it started. Loops that
+//! don't, such as `[>]`, are written with explicit `ptr += n`, and offsets start again from 0.
+//!
+//! ```text
+//! c[1] += 3;
+//! while c[0] {
+//!     c[0] -= 1;
+//!     c[2] += c[1] * 2;
+//! }
+//! ```
+
+use std::fmt::Write;
+
+use crate::optimizer::*;
+use crate::program::*;
+use crate::settings::*;
+
+/// Pseudocode of `program`, run with `settings` from an all-zero tape.
+pub fn explain(program: &Program, settings: &Settings) -> String {
+    let mut explainer = Explainer {
+        text: String::new(),
+        depth: 0,
+    };
+    explainer.block(&optimize(program, settings, &[]), 0);
+    explainer.text
+}
+
+struct Explainer {
+    text: String,
+    depth: usize,
+}
+
+impl Explainer {
+    fn line(&mut self, line: &str) {
+        writeln!(self.text, "{}{}", "    ".repeat(self.depth), line).unwrap();
+    }
+
+    /// Explain `ops`, starting with the pointer at `offset` from the cell written `c[0]`. Return
+    /// the offset after them.
+    fn block(&mut self, ops: &[Op], mut offset: isize) -> isize {
+        for op in ops {
+            match op {
+                Op::Move(val) => offset += val,
+                Op::Add(val) => {
+                    let (op, val) = add(*val);
+                    self.line(&format!("{} {} {};", cell(offset), op, val))
+                }
+                Op::Read => self.line(&format!("{} = read();", cell(offset))),
+                Op::Write => self.line(&format!("print({});", cell(offset))),
+                Op::Clear => self.line(&format!("{} = 0;", cell(offset))),
+                Op::Multiply(targets) => {
+                    for (target, factor) in targets {
+                        let (op, factor) = add(*factor);
+                        let line = if factor == 1 {
+                            format!("{} {} {};", cell(offset + target), op, cell(offset))
+                        } else {
+                            let target = cell(offset + target);
+                            format!("{} {} {} * {};", target, op, cell(offset), factor)
+                        };
+                        self.line(&line);
+                    }
+                    self.line(&format!("{} = 0;", cell(offset)));
+                }
+                Op::Loop(body) if balanced(body) => {
+                    self.line(&format!("while {} {{", cell(offset)));
+                    self.depth += 1;
+                    self.block(body, offset);
+                    self.depth -= 1;
+                    self.line("}");
+                }
+                Op::Loop(body) => {
+                    if offset != 0 {
+                        self.line(&format!("ptr {};", shift(offset)));
+                        offset = 0;
+                    }
+
+                    self.line("while c[0] {");
+                    self.depth += 1;
+                    let end = self.block(body, 0);
+                    if end != 0 {
+                        self.line(&format!("ptr {};", shift(end)));
+                    }
+                    self.depth -= 1;
+                    self.line("}");
+                }
+            }
+        }
+        offset
+    }
+}
+
+/// Whether `ops` always end with the pointer where it started
+fn balanced(ops: &[Op]) -> bool {
+    let mut offset = 0;
+    for op in ops {
+        match op {
+            Op::Move(val) => offset += val,
+            Op::Loop(body) if !balanced(body) => return false,
+            _ => {}
+        }
+    }
+    offset == 0
+}
+
+fn cell(offset: isize) -> String {
+    format!("c[{}]", offset)
+}
+
+/// Operator and amount adding `val` to a cell, wrapping around
+fn add(val: u8) -> (&'static str, usize) {
+    if val <= 128 {
+        ("+=", val as usize)
+    } else {
+        ("-=", 256 - val as usize)
+    }
+}
+
+/// Operator and operand moving the pointer by `offset`
+fn shift(offset: isize) -> String {
+    if offset > 0 {
+        format!("+= {}", offset)
+    } else {
+        format!("-= {}", -offset)
+    }
+}
@@ -0,0 +1,391 @@
+//! Symbolic execution, generating inputs that reach every loop edge and error of a program.
+//!
+//! Input is not given but read as unknown bytes. Since cells only change by `+` and `-`, every cell
+//! holds either a known value or an input byte plus a constant. When a loop condition depends on an
+//! input byte, the path forks in two: one where the byte makes the cell zero, and one where it
+//! doesn't. Each input byte keeps the set of values still allowed on its path, and the first path
+//! reaching a target gives its input, with every byte picked from its allowed values.
+//!
+//! Every read also forks into a path where the input ends there, and in digit mode into one where
+//! the input is not a number. Paths are explored breadth-first, so shorter inputs come first.
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use crate::cfg::*;
+use crate::location::*;
+use crate::program::*;
+use crate::settings::*;
+
+/// Default number of commands run over all paths before giving up
+pub const EXPLORE_STEPS: u64 = 1_000_000;
+
+/// Number of commands run on a single path without forking before giving up on it
+const PATH_STEPS: u64 = 100_000;
+
+/// Maximum number of paths waiting to be explored. Forks beyond it are dropped.
+const MAX_PATHS: usize = 10_000;
+
+/// Preferred values of input bytes in ASCII mode, so generated inputs are readable when possible
+const PREFERRED: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// What an input makes the program do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// Take this edge of the loop starting with the '['
+    Loop(EdgeKind),
+    /// Access a cell outside of the array at the command
+    IndexOutOfBound,
+    /// Read something that is not a number in digit input mode at the ','
+    ParseNumError,
+}
+
+/// Input reaching a target
+#[derive(Clone, Debug)]
+pub struct Case {
+    pub target: Target,
+    /// Index of the '[' for loop edges, or of the failing command
+    pub command_ptr: usize,
+    pub location: Location,
+    pub input: Vec<u8>,
+}
+
+/// Cell value
+#[derive(Clone, Copy, Debug)]
+enum Value {
+    Known(u8),
+    /// Input byte with the given index plus a constant
+    Input(usize, u8),
+}
+
+impl Value {
+    fn add(self, val: u8) -> Self {
+        match self {
+            Value::Known(known) => Value::Known(known.wrapping_add(val)),
+            Value::Input(input, constant) => Value::Input(input, constant.wrapping_add(val)),
+        }
+    }
+}
+
+/// Execution path
+#[derive(Clone)]
+struct Path {
+    command_ptr: usize,
+    cell_ptr: usize,
+    /// Cells visited so far. Cells after them are all zero.
+    cells: Vec<Value>,
+    /// Length of the array
+    len: usize,
+    /// Values each input byte read so far can still take
+    inputs: Vec<[bool; 256]>,
+    /// Whether the input ended
+    eof: bool,
+}
+
+impl Path {
+    /// Restrict input byte `input` to the values allowed by `allowed`. Cells holding it become
+    /// known once a single value is left.
+    fn restrict(&mut self, input: usize, allowed: impl Fn(u8) -> bool) {
+        for val in 0..=255 {
+            if !allowed(val) {
+                self.inputs[input][val as usize] = false;
+            }
+        }
+
+        let mut values = (0..=255u8).filter(|val| self.inputs[input][*val as usize]);
+        if let (Some(known), None) = (values.next(), values.next()) {
+            for cell in &mut self.cells {
+                if let Value::Input(other, constant) = *cell {
+                    if other == input {
+                        *cell = Value::Known(known.wrapping_add(constant));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Find inputs reaching the edges of every loop, and out-of-bound accesses and number parsing
+/// errors, running at most `max_steps` commands over all paths.
+pub fn explore(program: &Program, settings: &Settings, max_steps: u64) -> Vec<Case> {
+    let mut explorer = Explorer {
+        program,
+        settings,
+        paths: VecDeque::new(),
+        reached: HashSet::new(),
+        cases: Vec::new(),
+        steps: 0,
+        max_steps,
+    };
+    explorer.paths.push_back(Path {
+        command_ptr: 0,
+        cell_ptr: 0,
+        cells: Vec::new(),
+        len: settings.array_size,
+        inputs: Vec::new(),
+        eof: false,
+    });
+
+    while let Some(path) = explorer.paths.pop_front() {
+        if explorer.steps >= max_steps {
+            break;
+        }
+        explorer.run(path);
+    }
+
+    let mut cases = explorer.cases;
+    cases.sort_by_key(|case| case.command_ptr);
+    cases
+}
+
+struct Explorer<'a> {
+    program: &'a Program,
+    settings: &'a Settings,
+    /// Paths waiting to be explored
+    paths: VecDeque<Path>,
+    reached: HashSet<(Target, usize)>,
+    cases: Vec<Case>,
+    steps: u64,
+    max_steps: u64,
+}
+
+impl Explorer<'_> {
+    /// Run `path` until it stops or forks, and queue the paths it forks into.
+    fn run(&mut self, mut path: Path) {
+        for _ in 0..PATH_STEPS {
+            if self.steps >= self.max_steps {
+                return;
+            }
+            self.steps += 1;
+
+            let mut next = self.step(path);
+            if next.len() != 1 {
+                let room = MAX_PATHS.saturating_sub(self.paths.len());
+                self.paths.extend(next.into_iter().take(room));
+                return;
+            }
+            path = next.pop().unwrap();
+        }
+    }
+
+    /// Execute the current command of `path`. Return the paths it continues as.
+    fn step(&mut self, mut path: Path) -> Vec<Path> {
+        let command_ptr = path.command_ptr;
+        path.command_ptr += 1;
+
+        match self.program.commands[command_ptr] {
+            Command::Halt => Vec::new(),
+            Command::Right => {
+                if path.cell_ptr.wrapping_add(1) == path.len
+                    && self.settings.wrapping
+                    && !self.settings.dynamic_size
+                {
+                    path.cell_ptr = 0;
+                } else {
+                    path.cell_ptr = path.cell_ptr.wrapping_add(1);
+                }
+                vec![path]
+            }
+            Command::Left => {
+                if path.cell_ptr == 0 && self.settings.wrapping {
+                    path.cell_ptr = path.len.wrapping_sub(1);
+                } else {
+                    path.cell_ptr = path.cell_ptr.wrapping_sub(1);
+                }
+                vec![path]
+            }
+            Command::Inc | Command::Dec => {
+                let val = match self.program.commands[command_ptr] {
+                    Command::Inc => 1,
+                    _ => u8::MAX,
+                };
+                match self.cell(&mut path, command_ptr) {
+                    Some(cell) => {
+                        path.cells[cell] = path.cells[cell].add(val);
+                        vec![path]
+                    }
+                    None => Vec::new(),
+                }
+            }
+            Command::Open(close) => self.branch(path, command_ptr, command_ptr, |zero, path| {
+                if zero {
+                    path.command_ptr = close + 1;
+                    EdgeKind::Skip
+                } else {
+                    EdgeKind::Enter
+                }
+            }),
+            Command::Close(open) => self.branch(path, command_ptr, open, |zero, path| {
+                if zero {
+                    EdgeKind::Exit
+                } else {
+                    path.command_ptr = open + 1;
+                    EdgeKind::Repeat
+                }
+            }),
+            Command::Read => self.read(path, command_ptr),
+            Command::Write => match self.cell(&mut path, command_ptr) {
+                Some(_) => vec![path],
+                None => Vec::new(),
+            },
+        }
+    }
+
+    /// Fork `path` on whether the current cell is zero. `jump` moves each path to where it
+    /// continues and returns the loop edge it takes.
+    fn branch(
+        &mut self,
+        mut path: Path,
+        command_ptr: usize,
+        open: usize,
+        jump: impl Fn(bool, &mut Path) -> EdgeKind,
+    ) -> Vec<Path> {
+        let cell = match self.cell(&mut path, command_ptr) {
+            Some(cell) => cell,
+            None => return Vec::new(),
+        };
+
+        let mut paths = Vec::new();
+        match path.cells[cell] {
+            Value::Known(known) => paths.push((known == 0, path)),
+            Value::Input(input, constant) => {
+                // the cell is zero when the input byte is this value
+                let zero = 0u8.wrapping_sub(constant);
+                if path.inputs[input][zero as usize] {
+                    let mut zero_path = path.clone();
+                    zero_path.restrict(input, |val| val == zero);
+                    paths.push((true, zero_path));
+                }
+                path.restrict(input, |val| val != zero);
+                if path.inputs[input].iter().any(|allowed| *allowed) {
+                    paths.push((false, path));
+                }
+            }
+        }
+
+        paths
+            .into_iter()
+            .map(|(zero, mut path)| {
+                let edge = jump(zero, &mut path);
+                self.reach(Target::Loop(edge), open, &path, b"");
+                path
+            })
+            .collect()
+    }
+
+    /// Fork `path` on what a ',' reads: a new input byte, the end of the input, or in digit mode
+    /// something that is not a number.
+    fn read(&mut self, path: Path, command_ptr: usize) -> Vec<Path> {
+        let mut paths = Vec::new();
+
+        if !path.eof {
+            let mut byte_path = path.clone();
+            byte_path.inputs.push(self.initial());
+            let input = byte_path.inputs.len() - 1;
+            if let Some(cell) = self.cell(&mut byte_path, command_ptr) {
+                byte_path.cells[cell] = Value::Input(input, 0);
+                paths.push(byte_path);
+            }
+
+            if let InputMode::Digit = self.settings.input_mode {
+                self.reach(Target::ParseNumError, command_ptr, &path, b"x");
+            }
+        }
+
+        let mut eof_path = path;
+        eof_path.eof = true;
+        let eof = match self.settings.eof_behavior {
+            EofBehavior::AsIs => None,
+            EofBehavior::NegativeOne => Some(u8::MAX),
+            EofBehavior::Zero => Some(0),
+        };
+        match eof {
+            Some(val) => {
+                if let Some(cell) = self.cell(&mut eof_path, command_ptr) {
+                    eof_path.cells[cell] = Value::Known(val);
+                    paths.push(eof_path);
+                }
+            }
+            None => paths.push(eof_path),
+        }
+
+        paths
+    }
+
+    /// Values a new input byte can take. Carriage returns and ignored newlines are left out, since
+    /// reading them doesn't give a byte of their own.
+    fn initial(&self) -> [bool; 256] {
+        let mut allowed = [true; 256];
+        if let InputMode::Ascii = self.settings.input_mode {
+            match self.settings.newline_mode {
+                NewlineMode::CRLF => allowed[b'\r' as usize] = false,
+                NewlineMode::LF if self.settings.ignore_newline => allowed[b'\n' as usize] = false,
+                NewlineMode::LF => {}
+            }
+        }
+        allowed
+    }
+
+    /// Index of the current cell of `path`, visiting it. Record an out-of-bound access at the
+    /// command if there is no such cell.
+    fn cell(&mut self, path: &mut Path, command_ptr: usize) -> Option<usize> {
+        let cell_ptr = path.cell_ptr;
+        if cell_ptr >= path.len {
+            // pointers left of the first cell wrap around to the end of `usize`
+            if !self.settings.dynamic_size || cell_ptr > isize::MAX as usize {
+                self.reach(Target::IndexOutOfBound, command_ptr, path, b"");
+                return None;
+            }
+            path.len = cell_ptr + 1;
+        }
+
+        if cell_ptr >= path.cells.len() {
+            path.cells.resize(cell_ptr + 1, Value::Known(0));
+        }
+        Some(cell_ptr)
+    }
+
+    /// Record that `path` reaches `target` at the command, with `extra` input after the bytes
+    /// it read.
+    fn reach(&mut self, target: Target, command_ptr: usize, path: &Path, extra: &[u8]) {
+        if !self.reached.insert((target, command_ptr)) {
+            return;
+        }
+
+        let mut input = Vec::new();
+        for allowed in &path.inputs {
+            match self.settings.input_mode {
+                InputMode::Ascii => {
+                    let mut values = PREFERRED.iter().copied().chain(0..=255);
+                    input.push(values.find(|val| allowed[*val as usize]).unwrap());
+                }
+                InputMode::Digit => {
+                    let val = (0..=255u8).find(|val| allowed[*val as usize]).unwrap();
+                    input.extend(val.to_string().bytes());
+                    match self.settings.newline_mode {
+                        NewlineMode::CRLF => input.extend(b"\r\n"),
+                        NewlineMode::LF => input.push(b'\n'),
+                    }
+                }
+            }
+        }
+        input.extend(extra);
+
+        self.cases.push(Case {
+            target,
+            command_ptr,
+            location: self.program.locations[command_ptr],
+            input,
+        });
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Loop(edge) => write!(f, "{}", edge),
+            Target::IndexOutOfBound => write!(f, "index out of bound"),
+            Target::ParseNumError => write!(f, "parse error"),
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Source code formatter. Code is indented by loop nesting depth, runs of the same command are
+//! grouped together, and comments are kept.
+//!
+//! Rules:
+//!
+//! - Short loops without nested loops or comments stay on one line, e.g. `[->+<]`. Other loops
+//!   have their brackets on their own lines, with the body indented.
+//! - Runs of the same command are separated from each other by a space, e.g. `++++ > ++`.
+//! - Line breaks of the original source are kept, blank lines are collapsed into one.
+//! - Comments on their own line stay on their own line, and a comment at the start of a line stays
+//!   before the code following it. Other comments on a line with code are moved to the end of the
+//!   line, e.g. `hello + world` becomes `hello +  world`.
+//! - Code is wrapped so lines are no longer than the chosen width. Comments are never wrapped.
+
+use crate::program::*;
+
+/// Options for formatting
+#[derive(Clone)]
+pub struct FormatOptions {
+    /// Maximum line width
+    pub width: usize,
+    /// Number of spaces per nesting level
+    pub indent: usize,
+    /// Maximum length of a loop kept on one line, including its brackets
+    pub inline_loop: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            indent: 2,
+            inline_loop: 16,
+        }
+    }
+}
+
+/// Piece of source code
+enum Token {
+    /// Command, with the index of the command in the program
+    Command(char, usize),
+    Comment(String),
+    Newline,
+}
+
+/// Format `src`. `program` must be compiled from `src`, it tells which characters are commands and
+/// how brackets match.
+pub fn format(src: &str, program: &Program, options: &FormatOptions) -> String {
+    let newline = if src.contains("\r\n") { "\r\n" } else { "\n" };
+    let tokens = tokenize(src, program);
+
+    let mut formatter = Formatter {
+        options,
+        lines: Vec::new(),
+        depth: 0,
+        code: String::new(),
+        code_depth: 0,
+        bracket: false,
+        prefix: String::new(),
+        comment: String::new(),
+    };
+
+    let mut i = 0;
+    let mut line_start = true;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Command('[', _) => match inline_loop(&tokens, i, program, options) {
+                Some((text, end)) => {
+                    formatter.group(&text);
+                    i = end;
+                }
+                None => {
+                    formatter.bracket('[');
+                    formatter.depth += 1;
+                }
+            },
+            Token::Command(']', _) => {
+                formatter.flush();
+                formatter.depth = formatter.depth.saturating_sub(1);
+                formatter.bracket(']');
+            }
+            Token::Command(ch, _) => {
+                let mut count = 1;
+                while matches!(tokens.get(i + 1), Some(Token::Command(next, _)) if next == ch) {
+                    count += 1;
+                    i += 1;
+                }
+                formatter.run(*ch, count);
+            }
+            Token::Comment(text) => {
+                if line_start && formatter.code.is_empty() {
+                    formatter.flush();
+                    if matches!(tokens.get(i + 1), Some(Token::Command(..))) {
+                        formatter.prefix = text.clone();
+                    } else {
+                        formatter.line(text);
+                    }
+                } else {
+                    if !formatter.comment.is_empty() {
+                        formatter.comment.push(' ');
+                    }
+                    formatter.comment.push_str(text);
+                }
+            }
+            Token::Newline => {
+                formatter.flush();
+                if matches!(tokens.get(i + 1), Some(Token::Newline))
+                    && formatter.lines.last().is_some_and(|line| !line.is_empty())
+                {
+                    formatter.lines.push(String::new());
+                }
+            }
+        }
+
+        line_start = matches!(tokens[i], Token::Newline);
+        i += 1;
+    }
+    formatter.flush();
+
+    while formatter.lines.last().is_some_and(|line| line.is_empty()) {
+        formatter.lines.pop();
+    }
+
+    let mut formatted = formatter.lines.join(newline);
+    formatted.push_str(newline);
+    formatted
+}
+
+/// Split `src` into commands, comments and line breaks
+fn tokenize(src: &str, program: &Program) -> Vec<Token> {
+    let mut commands = program.locations[..program.commands.len() - 1]
+        .iter()
+        .map(|loc| loc.index)
+        .enumerate()
+        .peekable();
+
+    let mut tokens = Vec::new();
+    let mut comment = String::new();
+    for (i, ch) in src.chars().enumerate() {
+        let is_command = commands.peek().is_some_and(|(_, index)| *index == i);
+        if is_command || ch == '\n' {
+            let text = comment.trim();
+            if !text.is_empty() {
+                tokens.push(Token::Comment(String::from(text)));
+            }
+            comment.clear();
+        }
+
+        if is_command {
+            let (command, _) = commands.next().unwrap();
+            tokens.push(Token::Command(ch, command));
+        } else if ch == '\n' {
+            tokens.push(Token::Newline);
+        } else if ch != '\r' {
+            comment.push(ch);
+        }
+    }
+
+    let text = comment.trim();
+    if !text.is_empty() {
+        tokens.push(Token::Comment(String::from(text)));
+    }
+    tokens
+}
+
+/// If the loop opened by the token at `start` can stay on one line, return its text and the index
+/// of the token closing it.
+fn inline_loop(
+    tokens: &[Token],
+    start: usize,
+    program: &Program,
+    options: &FormatOptions,
+) -> Option<(String, usize)> {
+    let open = match tokens[start] {
+        Token::Command(_, command) => command,
+        _ => return None,
+    };
+    let close = match program.commands[open] {
+        Command::Open(close) => close,
+        _ => return None,
+    };
+    if close - open + 1 > options.inline_loop {
+        return None;
+    }
+
+    let mut text = String::from("[");
+    for (i, token) in tokens.iter().enumerate().skip(start + 1) {
+        match token {
+            Token::Command(']', command) if *command == close => {
+                text.push(']');
+                return Some((text, i));
+            }
+            Token::Command('[', _) | Token::Comment(_) => return None,
+            Token::Command(ch, _) => text.push(*ch),
+            Token::Newline => {}
+        }
+    }
+    None
+}
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    lines: Vec<String>,
+    depth: usize,
+    /// Code of the current line
+    code: String,
+    /// Depth of the current line, set when its code starts
+    code_depth: usize,
+    /// Whether the current line is a bracket on its own line, which takes no more code
+    bracket: bool,
+    /// Comment at the start of the current line, before its code
+    prefix: String,
+    /// Comment at the end of the current line
+    comment: String,
+}
+
+impl Formatter<'_> {
+    /// Add a whole line at the current depth
+    fn line(&mut self, text: &str) {
+        let indent = self.depth * self.options.indent;
+        self.lines.push(format!("{}{}", " ".repeat(indent), text));
+    }
+
+    /// End the current line. A comment at the start of the line is kept for the code following
+    /// it.
+    fn flush(&mut self) {
+        let mut line = match (self.code.is_empty(), self.comment.is_empty()) {
+            (true, true) => return,
+            (false, true) => self.code.clone(),
+            (true, false) => self.comment.clone(),
+            (false, false) => format!("{}  {}", self.code, self.comment),
+        };
+        if !self.code.is_empty() && !self.prefix.is_empty() {
+            line = format!("{} {}", self.prefix, line);
+            self.prefix.clear();
+        }
+
+        let indent = if self.code.is_empty() {
+            self.depth
+        } else {
+            self.code_depth
+        } * self.options.indent;
+        self.lines.push(format!("{}{}", " ".repeat(indent), line));
+        self.code.clear();
+        self.comment.clear();
+        self.bracket = false;
+    }
+
+    /// Start a line with a bracket on its own
+    fn bracket(&mut self, ch: char) {
+        self.flush();
+        self.code_depth = self.depth;
+        self.code.push(ch);
+        self.bracket = true;
+    }
+
+    /// Make room for `len` more characters of code on the current line, starting a new line if
+    /// needed
+    fn reserve(&mut self, len: usize) {
+        let prefix = match self.prefix.chars().count() {
+            0 => 0,
+            len => len + 1,
+        };
+        let width = self.depth * self.options.indent + prefix + self.code.chars().count() + len;
+        if self.bracket || (!self.code.is_empty() && width > self.options.width) {
+            self.flush();
+        }
+        if self.code.is_empty() {
+            self.code_depth = self.depth;
+        }
+    }
+
+    /// Add a run of `count` times the same command. The run is only split if it does not fit on
+    /// a line of its own.
+    fn run(&mut self, ch: char, count: usize) {
+        let joined = self.code.is_empty() || (self.code.ends_with(ch) && !self.bracket);
+        self.reserve(count + if joined { 0 } else { 1 });
+
+        for _ in 0..count {
+            self.command(ch);
+        }
+    }
+
+    /// Add a command, continuing the current run if it is the same command
+    fn command(&mut self, ch: char) {
+        let same_run = self.code.ends_with(ch) && !self.bracket;
+        self.reserve(if same_run { 1 } else { 2 });
+
+        if !self.code.is_empty() && !self.code.ends_with(ch) {
+            self.code.push(' ');
+        }
+        self.code.push(ch);
+    }
+
+    /// Add a group of commands that must not be split, e.g. a short loop
+    fn group(&mut self, text: &str) {
+        self.reserve(1 + text.chars().count());
+
+        if !self.code.is_empty() {
+            self.code.push(' ');
+        }
+        self.code.push_str(text);
+    }
+}
@@ -0,0 +1,290 @@
+//! Generator of programs printing a given text.
+//!
+//! A multiplication loop first sets a few cells close to the bytes of the text, e.g.
+//! `++++++++[>+++++++++>++++++++++++<<-]` sets cell 1 to 72 and cell 2 to 96. Each byte is then
+//! printed from the cell needing the fewest moves and additions, and that cell keeps the byte for
+//! the next ones. Every number of cells and loop counter is tried, and the shortest program wins.
+
+/// Largest loop counter tried
+const MAX_COUNTER: usize = 32;
+
+/// Number of loops improved one factor at a time, out of the shortest ones
+const IMPROVED: usize = 16;
+
+/// Options for generating text
+#[derive(Clone)]
+pub struct TextOptions {
+    /// Maximum number of cells used, including the loop counter
+    pub cells: usize,
+    /// Whether cells may overflow or underflow. Without wrapping, the program also runs on
+    /// interpreters whose cells don't wrap around.
+    pub wrapping: bool,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self {
+            cells: 16,
+            wrapping: true,
+        }
+    }
+}
+
+/// Generate a program printing `text`.
+pub fn generate_text(text: &[u8], options: &TextOptions) -> String {
+    let mut candidates = vec![(length(text, &[], 0, options), Vec::new(), 0)];
+
+    let groups = Groups::new(text);
+    for count in 1..options.cells.max(1) {
+        let targets = match groups.split(count) {
+            Some(targets) => targets,
+            None => break,
+        };
+
+        for counter in 2..=MAX_COUNTER {
+            let factors = factors(&targets, counter, options);
+            candidates.push((length(text, &factors, counter, options), factors, counter));
+        }
+    }
+
+    // only the most promising loops are improved
+    candidates.sort_by_key(|(len, _, _)| *len);
+    let mut best = (Vec::new(), 0);
+    let mut best_len = usize::MAX;
+    for (_, factors, counter) in candidates.into_iter().take(IMPROVED) {
+        let (factors, len) = improve(text, factors, counter, options);
+        if len < best_len {
+            best = (factors, counter);
+            best_len = len;
+        }
+    }
+
+    let (factors, counter) = best;
+    let mut code = String::new();
+    generate(text, &factors, counter, options, |ch, count| {
+        code.extend(std::iter::repeat_n(ch, count))
+    });
+    code
+}
+
+/// Loop factors setting cells as close as possible to `targets` with `counter` iterations
+fn factors(targets: &[u8], counter: usize, options: &TextOptions) -> Vec<isize> {
+    let counter = counter as isize;
+    targets
+        .iter()
+        .map(|target| {
+            let target = *target as isize;
+            let mut factors = vec![target / counter, (target + counter - 1) / counter];
+            if options.wrapping {
+                factors.push(-((256 - target) / counter));
+                factors.push(-((256 - target + counter - 1) / counter));
+            }
+
+            factors
+                .into_iter()
+                .filter(|factor| valid(*factor, counter, options))
+                .min_by_key(|factor| {
+                    let value = (factor * counter).rem_euclid(256) as u8;
+                    factor.unsigned_abs() + adjustment(value, target as u8, options).1
+                })
+                .unwrap()
+        })
+        .collect()
+}
+
+/// Starting from `factors`, change factors one at a time while the program gets shorter. Return
+/// the best factors and the length of their program.
+fn improve(
+    text: &[u8],
+    mut factors: Vec<isize>,
+    counter: usize,
+    options: &TextOptions,
+) -> (Vec<isize>, usize) {
+    let mut best = length(text, &factors, counter, options);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 0..factors.len() {
+            for delta in [-1, 1] {
+                let factor = factors[i] + delta;
+                if !valid(factor, counter as isize, options) {
+                    continue;
+                }
+
+                let old = std::mem::replace(&mut factors[i], factor);
+                let len = length(text, &factors, counter, options);
+                if len < best {
+                    best = len;
+                    improved = true;
+                } else {
+                    factors[i] = old;
+                }
+            }
+        }
+    }
+
+    (factors, best)
+}
+
+/// Whether a loop factor keeps cells in range
+fn valid(factor: isize, counter: isize, options: &TextOptions) -> bool {
+    options.wrapping || (0..=255).contains(&(factor * counter))
+}
+
+/// Length of the program generated with the given loop
+fn length(text: &[u8], factors: &[isize], counter: usize, options: &TextOptions) -> usize {
+    let mut len = 0;
+    generate(text, factors, counter, options, |_, count| len += count);
+    len
+}
+
+/// Generate a program printing `text`, passing each run of the same command to `emit`.
+///
+/// A multiplication loop first adds `factors` to cells 1 and on, `counter` times, using cell 0 as
+/// the loop counter. Cells after the last one changed by the loop are left out of it, and without
+/// any factor there is no loop.
+fn generate(
+    text: &[u8],
+    factors: &[isize],
+    counter: usize,
+    options: &TextOptions,
+    mut emit: impl FnMut(char, usize),
+) {
+    let mut values = vec![0];
+    values.extend(
+        factors
+            .iter()
+            .map(|factor| (factor * counter as isize).rem_euclid(256) as u8),
+    );
+
+    if let Some(last) = factors.iter().rposition(|factor| *factor != 0) {
+        emit('+', counter);
+        emit('[', 1);
+        for factor in &factors[..=last] {
+            emit('>', 1);
+            emit(if *factor < 0 { '-' } else { '+' }, factor.unsigned_abs());
+        }
+        emit('<', last + 1);
+        emit('-', 1);
+        emit(']', 1);
+    }
+
+    let mut ptr = 0;
+    for byte in text {
+        let (cell, _) = values
+            .iter()
+            .enumerate()
+            .map(|(cell, value)| {
+                let cost = cell.abs_diff(ptr) + adjustment(*value, *byte, options).1;
+                (cell, cost)
+            })
+            .min_by_key(|(_, cost)| *cost)
+            .unwrap();
+
+        emit(if cell > ptr { '>' } else { '<' }, cell.abs_diff(ptr));
+        ptr = cell;
+
+        let (ch, count) = adjustment(values[cell], *byte, options);
+        emit(ch, count);
+        emit('.', 1);
+        values[cell] = *byte;
+    }
+}
+
+/// Command and number of times to repeat it to change a cell from `from` to `to`
+fn adjustment(from: u8, to: u8, options: &TextOptions) -> (char, usize) {
+    let up = to.wrapping_sub(from) as usize;
+    if options.wrapping {
+        if up <= 128 {
+            ('+', up)
+        } else {
+            ('-', 256 - up)
+        }
+    } else if to >= from {
+        ('+', (to - from) as usize)
+    } else {
+        ('-', (from - to) as usize)
+    }
+}
+
+/// Distinct bytes of a text with how many times each appears, for splitting them into groups of
+/// close values
+struct Groups {
+    /// Distinct bytes, in increasing order
+    bytes: Vec<u8>,
+    counts: Vec<usize>,
+}
+
+impl Groups {
+    fn new(text: &[u8]) -> Self {
+        let mut counts = [0; 256];
+        for byte in text {
+            counts[*byte as usize] += 1;
+        }
+
+        let bytes: Vec<u8> = (0..=255)
+            .filter(|byte| counts[*byte as usize] > 0)
+            .collect();
+        let counts = bytes.iter().map(|byte| counts[*byte as usize]).collect();
+        Self { bytes, counts }
+    }
+
+    /// Split the bytes into `count` groups of consecutive bytes, minimizing the total distance of
+    /// every byte of the text to the median of its group. Return the medians, or `None` if there
+    /// are not enough distinct bytes.
+    fn split(&self, count: usize) -> Option<Vec<u8>> {
+        let len = self.bytes.len();
+        if count > len {
+            return None;
+        }
+
+        // best[groups][end]: cost and start of the last group, for the first `end` bytes
+        let mut best = vec![vec![(usize::MAX, 0); len + 1]; count + 1];
+        best[0][0] = (0, 0);
+        for groups in 1..=count {
+            for end in groups..=len {
+                for start in groups - 1..end {
+                    let (prev, _) = best[groups - 1][start];
+                    if prev == usize::MAX {
+                        continue;
+                    }
+                    let cost = prev + self.cost(start, end);
+                    if cost < best[groups][end].0 {
+                        best[groups][end] = (cost, start);
+                    }
+                }
+            }
+        }
+
+        let mut medians = Vec::new();
+        let mut end = len;
+        for groups in (1..=count).rev() {
+            let start = best[groups][end].1;
+            medians.push(self.median(start, end));
+            end = start;
+        }
+        medians.reverse();
+        Some(medians)
+    }
+
+    /// Weighted median of the bytes in `start..end`
+    fn median(&self, start: usize, end: usize) -> u8 {
+        let total: usize = self.counts[start..end].iter().sum();
+        let mut seen = 0;
+        for i in start..end {
+            seen += self.counts[i];
+            if seen * 2 >= total {
+                return self.bytes[i];
+            }
+        }
+        self.bytes[end - 1]
+    }
+
+    /// Total distance of the bytes in `start..end` to their median
+    fn cost(&self, start: usize, end: usize) -> usize {
+        let median = self.median(start, end);
+        (start..end)
+            .map(|i| self.counts[i] * self.bytes[i].abs_diff(median) as usize)
+            .sum()
+    }
+}
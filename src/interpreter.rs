@@ -2,14 +2,20 @@ use std::io::prelude::*;
 
 use crate::cells::*;
 use crate::error::*;
+use crate::loop_detector::*;
+use crate::observer::*;
 use crate::program::*;
 use crate::settings::*;
+use crate::stdio_wrapper::*;
 
 /// State when running
+#[derive(Clone)]
 pub struct State {
     pub cells: Cells,
     pub command_ptr: usize,
     pub cell_ptr: usize,
+    /// Number of commands executed so far
+    pub steps: u64,
 }
 
 /// Read result
@@ -25,26 +31,105 @@ enum ReadResult {
 pub struct Interpreter {
     pub program: Program,
     pub settings: Settings,
+    /// Initial values of the cells. Empty means all zeros.
+    pub tape: Vec<u8>,
     pub reader: Box<dyn Read>,
     pub writer: Box<dyn Write>,
+    /// Observers notified after every executed command
+    pub observers: Vec<Box<dyn Observer>>,
+    /// Detector stopping the program when it repeats a state without I/O
+    pub loop_detector: Option<LoopDetector>,
 }
 
 impl Interpreter {
+    /// Create an interpreter for an already compiled program, reading from stdin and writing to
+    /// stdout. The same program can be reused for many interpreters without recompiling.
+    pub fn from_program(program: Program, settings: Settings) -> Self {
+        Self {
+            program,
+            settings,
+            tape: Vec::new(),
+            reader: Box::new(StdinWrapper::new()),
+            writer: Box::new(StdoutWrapper::new()),
+            observers: Vec::new(),
+            loop_detector: None,
+        }
+    }
+
     /// Return a new state for running.
     pub fn ready(&self) -> State {
         State {
-            cells: Cells::new(self.settings.array_size),
+            cells: Cells::with_tape(&self.tape, self.settings.array_size),
             command_ptr: 0,
             cell_ptr: 0,
+            steps: 0,
         }
     }
 
     /// Execute current instruction
     pub fn next(&mut self, state: &mut State) -> RunResult {
-        match self.program.0[state.command_ptr] {
+        let command_ptr = state.command_ptr;
+        let result = self.step(state);
+        if let (RunResult::None, Some(detector)) = (&result, &mut self.loop_detector) {
+            let command = self.program.commands[command_ptr];
+            if detector.detect(command, state.command_ptr, &state.cells, state.cell_ptr) {
+                return RunResult::InfiniteLoop {
+                    location: self.program.locations[state.command_ptr - 1],
+                };
+            }
+        }
+        result
+    }
+
+    /// Execute current instruction and notify observers
+    fn step(&mut self, state: &mut State) -> RunResult {
+        if self.observers.is_empty() {
+            let result = self.execute(state);
+            if let RunResult::None = result {
+                state.steps += 1;
+            }
+            return result;
+        }
+
+        let command_ptr = state.command_ptr;
+        let cell_ptr = state.cell_ptr;
+        let before = state.cells.0.get(cell_ptr).copied().unwrap_or(0);
+
+        let result = self.execute(state);
+        if let RunResult::None = result {
+            let step = Step {
+                step: state.steps,
+                command_ptr,
+                command: self.program.commands[command_ptr],
+                location: self.program.locations[command_ptr],
+                cell_ptr,
+                before,
+                after: state.cells.0.get(cell_ptr).copied().unwrap_or(0),
+            };
+            for observer in &mut self.observers {
+                observer.observe(&step);
+            }
+            state.steps += 1;
+        }
+        result
+    }
+
+    /// Notify observers that the program stopped.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        for observer in &mut self.observers {
+            observer.finish()?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, state: &mut State) -> RunResult {
+        match self.program.commands[state.command_ptr] {
             Command::Halt => return RunResult::Halted,
             Command::Right => {
-                if state.cell_ptr == state.cells.0.len() - 1 && !self.settings.dynamic_size {
+                if state.cell_ptr.wrapping_add(1) == state.cells.0.len()
+                    && self.settings.wrapping
+                    && !self.settings.dynamic_size
+                {
                     state.cell_ptr = 0;
                 } else {
                     state.cell_ptr = state.cell_ptr.wrapping_add(1);
@@ -52,7 +137,7 @@ impl Interpreter {
             }
             Command::Left => {
                 if state.cell_ptr == 0 && self.settings.wrapping {
-                    state.cell_ptr = state.cells.0.len() - 1;
+                    state.cell_ptr = state.cells.0.len().wrapping_sub(1);
                 } else {
                     state.cell_ptr = state.cell_ptr.wrapping_sub(1);
                 }
@@ -104,7 +189,7 @@ impl Interpreter {
                         },
                     },
                     ReadResult::Newline => match self.get_cell(state) {
-                        Some(data) => *data = '\n' as u8,
+                        Some(data) => *data = b'\n',
                         None => return RunResult::IndexOutOfBound(state.cell_ptr),
                     },
                     ReadResult::ReadFailed => return RunResult::ReadFailed,
@@ -114,24 +199,18 @@ impl Interpreter {
             Command::Write => {
                 let buf = match self.get_cell(state) {
                     Some(data) => {
-                        if *data == '\n' as u8
-                            && matches!(self.settings.newline_mode, NewlineMode::CRLF)
+                        if *data == b'\n' && matches!(self.settings.newline_mode, NewlineMode::CRLF)
                         {
-                            ['\r' as u8, '\n' as u8]
+                            vec![b'\r', b'\n']
                         } else {
-                            [*data, 0]
+                            vec![*data]
                         }
                     }
                     None => return RunResult::IndexOutOfBound(state.cell_ptr),
                 };
 
-                match self.writer.write(&buf) {
-                    Err(_) => return RunResult::WriteFailed,
-                    Ok(0) => return RunResult::WriteFailed,
-                    Ok(_) => match self.writer.flush() {
-                        Err(_) => return RunResult::WriteFailed,
-                        Ok(_) => {}
-                    },
+                if self.writer.write_all(&buf).is_err() || self.writer.flush().is_err() {
+                    return RunResult::WriteFailed;
                 }
             }
         }
@@ -139,11 +218,33 @@ impl Interpreter {
         RunResult::None
     }
 
+    /// Execute instructions until the program stops, and return the reason it stopped.
+    pub fn run(&mut self, state: &mut State) -> RunResult {
+        loop {
+            match self.next(state) {
+                RunResult::None => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// Execute instructions until the program stops or `state.steps` reaches `max_steps`, and
+    /// return the reason it stopped. `RunResult::None` is returned if it ran out of steps.
+    pub fn run_for(&mut self, state: &mut State, max_steps: u64) -> RunResult {
+        while state.steps < max_steps {
+            match self.next(state) {
+                RunResult::None => continue,
+                result => return result,
+            }
+        }
+        RunResult::None
+    }
+
     fn read_ascii(&mut self) -> ReadResult {
-        return match self.settings.newline_mode {
+        match self.settings.newline_mode {
             NewlineMode::CRLF => self.read_ascii_crlf(),
             NewlineMode::LF => self.read_ascii_lf(),
-        };
+        }
     }
 
     fn read_ascii_crlf(&mut self) -> ReadResult {
@@ -153,7 +254,7 @@ impl Interpreter {
             Err(_) => return ReadResult::ReadFailed,
             Ok(0) => return ReadResult::None,
             Ok(_) => {
-                if buf[0] != '\r' as u8 {
+                if buf[0] != b'\r' {
                     return ReadResult::Success(buf[0]);
                 }
             }
@@ -161,9 +262,9 @@ impl Interpreter {
 
         match self.reader.read(&mut buf) {
             Err(_) => return ReadResult::ReadFailed,
-            Ok(0) => return ReadResult::Success('\r' as u8),
+            Ok(0) => return ReadResult::Success(b'\r'),
             Ok(_) => {
-                if buf[0] != '\n' as u8 {
+                if buf[0] != b'\n' {
                     return ReadResult::Success(buf[0]);
                 } else if !self.settings.ignore_newline {
                     return ReadResult::Newline;
@@ -172,9 +273,9 @@ impl Interpreter {
         }
 
         match self.reader.read(&mut buf) {
-            Err(_) => return ReadResult::ReadFailed,
-            Ok(0) => return ReadResult::ReadFailed,
-            Ok(_) => return ReadResult::Success(buf[0]),
+            Err(_) => ReadResult::ReadFailed,
+            Ok(0) => ReadResult::ReadFailed,
+            Ok(_) => ReadResult::Success(buf[0]),
         }
     }
 
@@ -185,7 +286,7 @@ impl Interpreter {
             Err(_) => return ReadResult::ReadFailed,
             Ok(0) => return ReadResult::None,
             Ok(_) => {
-                if buf[0] != '\n' as u8 {
+                if buf[0] != b'\n' {
                     return ReadResult::Success(buf[0]);
                 } else if !self.settings.ignore_newline {
                     return ReadResult::Newline;
@@ -194,11 +295,9 @@ impl Interpreter {
         }
 
         match self.reader.read(&mut buf) {
-            Err(_) => return ReadResult::ReadFailed,
-            Ok(0) => return ReadResult::ReadFailed,
-            Ok(_) => {
-                return ReadResult::Success(buf[0]);
-            }
+            Err(_) => ReadResult::ReadFailed,
+            Ok(0) => ReadResult::ReadFailed,
+            Ok(_) => ReadResult::Success(buf[0]),
         }
     }
 
@@ -212,15 +311,15 @@ impl Interpreter {
                 Ok(0) => return ReadResult::None,
                 Ok(_) => match self.settings.newline_mode {
                     NewlineMode::CRLF => {
-                        if buf[0] == '\r' as u8 && !last_is_cr {
+                        if buf[0] == b'\r' && !last_is_cr {
                             // read '\r' first time
                             last_is_cr = true;
-                        } else if buf[0] == '\n' as u8 && last_is_cr {
+                        } else if buf[0] == b'\n' && last_is_cr {
                             // read '\n' right after '\r' (i.e. newline)
                             return ReadResult::Success(result);
                         } else if buf[0].is_ascii_digit() && !last_is_cr {
                             // read a digit
-                            result = result * 10 + buf[0] - '0' as u8;
+                            result = result.wrapping_mul(10).wrapping_add(buf[0] - b'0');
                             last_is_cr = false;
                         } else {
                             // read something else
@@ -228,10 +327,10 @@ impl Interpreter {
                         }
                     }
                     NewlineMode::LF => {
-                        if buf[0] == '\n' as u8 {
+                        if buf[0] == b'\n' {
                             return ReadResult::Success(result);
                         } else if buf[0].is_ascii_digit() {
-                            result = result * 10 + buf[0] - '0' as u8;
+                            result = result.wrapping_mul(10).wrapping_add(buf[0] - b'0');
                         } else {
                             return ReadResult::ParseNumError;
                         }
@@ -243,9 +342,9 @@ impl Interpreter {
 
     fn get_cell<'a>(&mut self, state: &'a mut State) -> Option<&'a mut u8> {
         if self.settings.dynamic_size {
-            return state.cells.get_mut_expand(state.cell_ptr);
+            state.cells.get_mut_expand(state.cell_ptr)
         } else {
-            return state.cells.get_mut(state.cell_ptr);
+            state.cells.get_mut(state.cell_ptr)
         }
     }
 }
@@ -1,32 +1,61 @@
+use std::cell::RefCell;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::Cursor;
+use std::rc::Rc;
 
-use crate::error::*;
 use crate::compiler::*;
+use crate::error::*;
 use crate::interpreter::*;
+use crate::loop_detector::*;
+use crate::observer::*;
+use crate::program::*;
 use crate::settings::*;
 use crate::stdio_wrapper::*;
 
+/// Where the program comes from
+enum Source {
+    Code(String),
+    Program(Program),
+}
+
 /// InterpreterBuilder
 pub struct InterpreterBuilder {
-    src: String,
+    src: Source,
     settings: Settings,
+    tape: Vec<u8>,
+    comment: Option<char>,
+    observers: Vec<Box<dyn Observer>>,
+    detect_loops: bool,
     reader: Box<dyn Read>,
     writer: Box<dyn Write>,
 }
 
 impl InterpreterBuilder {
     pub fn new(src: String, settings: Settings) -> Self {
+        Self::with_source(Source::Code(src), settings)
+    }
+
+    /// Build from an already compiled program instead of source code.
+    pub fn from_program(program: Program, settings: Settings) -> Self {
+        Self::with_source(Source::Program(program), settings)
+    }
+
+    fn with_source(src: Source, settings: Settings) -> Self {
         Self {
             src,
             settings,
+            tape: Vec::new(),
+            comment: None,
+            observers: Vec::new(),
+            detect_loops: false,
             reader: Box::new(StdinWrapper::new()),
             writer: Box::new(StdoutWrapper::new()),
         }
     }
 
     /// Set input file. Default to stdin.
-    pub fn reader<'a>(mut self, reader: Option<&'a str>) -> std::io::Result<Self> {
+    pub fn reader(mut self, reader: Option<&str>) -> std::io::Result<Self> {
         match reader {
             Some(reader) => self.reader = Box::new(File::open(reader)?),
             None => self.reader = Box::new(std::io::stdin()),
@@ -36,23 +65,74 @@ impl InterpreterBuilder {
     }
 
     /// Set output file. Default to stdout.
-    pub fn writer<'a>(mut self, writer: Option<&'a str>) -> std::io::Result<Self> {
+    pub fn writer(mut self, writer: Option<&str>) -> std::io::Result<Self> {
         match writer {
-            Some(writer) => self.writer = Box::new(File::open(writer)?),
+            Some(writer) => self.writer = Box::new(File::create(writer)?),
             None => self.writer = Box::new(std::io::stdout()),
         }
 
         Ok(self)
     }
 
-    pub fn build(self) -> Result<Interpreter, CompileError> {
-        let program = Compiler::new().compile(self.src)?;
+    /// Read input from the given bytes instead of a file.
+    pub fn input(mut self, input: Vec<u8>) -> Self {
+        self.reader = Box::new(Cursor::new(input));
+        self
+    }
+
+    /// Write output to the given buffer instead of a file, so it can be read after running.
+    pub fn output(mut self, output: Rc<RefCell<Vec<u8>>>) -> Self {
+        self.writer = Box::new(SharedBuffer::new(output));
+        self
+    }
+
+    /// Set initial values of the cells. Default to all zeros.
+    pub fn tape(mut self, tape: Vec<u8>) -> Self {
+        self.tape = tape;
+        self
+    }
+
+    /// Enable strict syntax, with line comments starting with `comment`. Default to non-strict,
+    /// where every unknown character is a comment.
+    pub fn strict(mut self, comment: char) -> Self {
+        self.comment = Some(comment);
+        self
+    }
+
+    /// Add an observer notified after every executed command.
+    pub fn observer(mut self, observer: Box<dyn Observer>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Stop with `RunResult::InfiniteLoop` when the program repeats a state at a loop head without
+    /// any I/O in between. Default to running forever.
+    pub fn detect_loops(mut self) -> Self {
+        self.detect_loops = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Interpreter, Vec<CompileError>> {
+        let program = match self.src {
+            Source::Code(src) => match self.comment {
+                Some(comment) => Compiler::new().strict(comment).compile(src)?,
+                None => Compiler::new().compile(src)?,
+            },
+            Source::Program(program) => program,
+        };
 
         Ok(Interpreter {
             program,
             settings: self.settings,
+            tape: self.tape,
             reader: self.reader,
             writer: self.writer,
+            observers: self.observers,
+            loop_detector: if self.detect_loops {
+                Some(LoopDetector::new())
+            } else {
+                None
+            },
         })
     }
 }
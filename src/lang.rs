@@ -0,0 +1,913 @@
+//! Small structured language compiled to Brainfuck source code.
+//!
+//! ```text
+//! // comments run to the end of the line
+//! var a;                  // variables hold one byte, starting at 0
+//! var b = 'A' + 2;
+//! read a;                 // read one byte into a variable
+//! while a != 0 {
+//!     if a >= '0' && a <= '9' {
+//!         print a - '0' + 'a';
+//!     } else if a == '\n' {
+//!         print "\n";
+//!     } else {
+//!         print a;
+//!     }
+//!     read a;
+//! }
+//! ```
+//!
+//! Expressions are made of numbers from 0 to 255, character literals, variables and parentheses,
+//! with these operators from lowest to highest precedence:
+//!
+//! - `||`, `&&`: logical or, and, giving 0 or 1
+//! - `==`, `!=`, `<`, `<=`, `>`, `>=`: comparisons, giving 0 or 1
+//! - `+`, `-`: addition and subtraction, wrapping around
+//! - `*`, `/`, `%`: multiplication, wrapping around, and division. `x / 0` is 0 and `x % 0` is
+//!   `x`.
+//! - `!`: logical not
+//!
+//! Variables are global, whether they are declared inside a block or not, and must be declared
+//! before use. `print` takes an expression, printing the byte it gives, or a string.
+//!
+//! Variables take the first cells of the array, and temporary values the cells after them. The
+//! generated code never moves left of cell 0 and relies on cells wrapping around.
+//!
+//! `read` gives 0 at the end of the input, with `--eof_behavior` either `as_is` or `zero`. Other
+//! settings change what it reads: `negative_one` gives 255 at the end of the input,
+//! `--ignore_newline` skips newlines, and the CRLF newline mode reads `\r\n` as a single `\n`.
+
+use std::collections::HashMap;
+
+use crate::error::*;
+use crate::location::*;
+
+/// Number of statements `brainrust lang` runs directly to check the compiled code against
+pub const LANG_STEPS: u64 = 100_000;
+
+/// Number of commands of the compiled code `brainrust lang` runs to check it
+pub const LANG_COMMANDS: u64 = 10_000_000;
+
+/// Input `brainrust lang` runs the compiled code on to check it, besides empty input
+pub const LANG_INPUT: &[u8] = b"Hello, World!\n0123456789";
+
+/// Compile `src` into Brainfuck source code, one line per statement.
+pub fn compile_lang(src: &str) -> Result<String, LangError> {
+    let statements = parse(src)?;
+    let mut generator = Generator {
+        code: String::new(),
+        depth: 0,
+        ptr: 0,
+        used: Vec::new(),
+        variables: HashMap::new(),
+    };
+    generator.block(&statements)?;
+    generator.code.push('\n');
+    Ok(generator.code)
+}
+
+/// Run `src` directly, without compiling it to Brainfuck, on `input`. Reading past the end of
+/// the input gives 0. Return what it prints, and whether it finished within `max_steps`
+/// statements. This is the reference the compiled code is checked against.
+pub fn run_lang(src: &str, input: &[u8], max_steps: u64) -> Result<(Vec<u8>, bool), LangError> {
+    // checks every variable is declared before use
+    compile_lang(src)?;
+
+    let mut runner = Runner {
+        input: input.iter(),
+        output: Vec::new(),
+        variables: HashMap::new(),
+        steps: max_steps,
+    };
+    let finished = runner.block(&parse(src)?);
+    Ok((runner.output, finished))
+}
+
+fn parse(src: &str) -> Result<Vec<Statement>, LangError> {
+    let tokens = tokenize(src)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut statements = Vec::new();
+    while !parser.at_end() {
+        statements.push(parser.statement()?);
+    }
+    Ok(statements)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u8),
+    Str(Vec<u8>),
+    Symbol(&'static str),
+    End,
+}
+
+const SYMBOLS: [&str; 20] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "*", "/", "%", "!", "=", "(", ")", "{",
+    "}", ";",
+];
+
+fn tokenize(src: &str) -> Result<Vec<(Token, Location)>, LangError> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut loc = Location {
+        index: 0,
+        line: 1,
+        column: 1,
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let start = loc;
+        let ch = chars[i];
+        let mut len = 1;
+
+        if ch.is_whitespace() {
+        } else if ch == '/' && chars.get(i + 1) == Some(&'/') {
+            while i + len < chars.len() && chars[i + len] != '\n' {
+                len += 1;
+            }
+        } else if ch.is_ascii_alphabetic() || ch == '_' {
+            while i + len < chars.len()
+                && (chars[i + len].is_ascii_alphanumeric() || chars[i + len] == '_')
+            {
+                len += 1;
+            }
+            let ident: String = chars[i..i + len].iter().collect();
+            tokens.push((Token::Ident(ident), start));
+        } else if ch.is_ascii_digit() {
+            while i + len < chars.len() && chars[i + len].is_ascii_digit() {
+                len += 1;
+            }
+            let number: String = chars[i..i + len].iter().collect();
+            match number.parse::<u8>() {
+                Ok(number) => tokens.push((Token::Number(number), start)),
+                Err(_) => return Err(LangError::InvalidNumber(start, number)),
+            }
+        } else if ch == '\'' || ch == '"' {
+            let mut bytes = Vec::new();
+            loop {
+                let next = match chars.get(i + len) {
+                    Some('\n') | None => return Err(LangError::InvalidLiteral(start)),
+                    Some(next) => *next,
+                };
+                len += 1;
+                if next == ch {
+                    break;
+                }
+
+                let next = if next == '\\' {
+                    len += 1;
+                    match chars.get(i + len - 1) {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        Some(escaped @ ('\\' | '\'' | '"')) => *escaped,
+                        _ => return Err(LangError::InvalidLiteral(start)),
+                    }
+                } else {
+                    next
+                };
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(next.encode_utf8(&mut buf).as_bytes());
+            }
+
+            if ch == '"' {
+                tokens.push((Token::Str(bytes), start));
+            } else if bytes.len() == 1 {
+                tokens.push((Token::Number(bytes[0]), start));
+            } else {
+                return Err(LangError::InvalidLiteral(start));
+            }
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            match SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+                Some(symbol) => {
+                    len = symbol.len();
+                    tokens.push((Token::Symbol(symbol), start));
+                }
+                None => return Err(LangError::InvalidChar(start, ch)),
+            }
+        }
+
+        for ch in &chars[i..i + len] {
+            loc.index += 1;
+            if *ch == '\n' {
+                loc.line += 1;
+                loc.column = 1;
+            } else {
+                loc.column += 1;
+            }
+        }
+        i += len;
+    }
+
+    tokens.push((Token::End, loc));
+    Ok(tokens)
+}
+
+enum Statement {
+    Var(String, Location, Option<Expr>),
+    Assign(String, Location, Expr),
+    If(Expr, Vec<Statement>, Vec<Statement>),
+    While(Expr, Vec<Statement>),
+    Print(Expr),
+    PrintStr(Vec<u8>),
+    Read(String, Location),
+}
+
+enum Expr {
+    Number(u8),
+    Var(String, Location),
+    Not(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// Binary operators by precedence level, from lowest to highest
+const LEVELS: [&[&str]; 5] = [
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<", "<=", ">", ">="],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser {
+    tokens: Vec<(Token, Location)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn location(&self) -> Location {
+        self.tokens[self.pos].1
+    }
+
+    fn at_end(&self) -> bool {
+        *self.peek() == Token::End
+    }
+
+    fn next(&mut self) -> (Token, Location) {
+        let token = self.tokens[self.pos].clone();
+        if !self.at_end() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Consume the given symbol if it is next
+    fn eat(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Token::Symbol(next) if *next == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), LangError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(LangError::Expected(self.location(), symbol))
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Token::Ident(ident) if ident == keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Result<(String, Location), LangError> {
+        match self.next() {
+            (Token::Ident(ident), loc) => Ok((ident, loc)),
+            (_, loc) => Err(LangError::Expected(loc, "variable name")),
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, LangError> {
+        self.expect("{")?;
+        let mut statements = Vec::new();
+        while !self.eat("}") {
+            if self.at_end() {
+                return Err(LangError::Expected(self.location(), "}"));
+            }
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, LangError> {
+        let statement = if self.keyword("var") {
+            let (name, loc) = self.ident()?;
+            let value = if self.eat("=") {
+                Some(self.expr(0)?)
+            } else {
+                None
+            };
+            Statement::Var(name, loc, value)
+        } else if self.keyword("if") {
+            return self.if_statement();
+        } else if self.keyword("while") {
+            let cond = self.expr(0)?;
+            return Ok(Statement::While(cond, self.block()?));
+        } else if self.keyword("print") {
+            match self.peek().clone() {
+                Token::Str(text) => {
+                    self.pos += 1;
+                    Statement::PrintStr(text)
+                }
+                _ => Statement::Print(self.expr(0)?),
+            }
+        } else if self.keyword("read") {
+            let (name, loc) = self.ident()?;
+            Statement::Read(name, loc)
+        } else {
+            let (name, loc) = self.ident()?;
+            self.expect("=")?;
+            Statement::Assign(name, loc, self.expr(0)?)
+        };
+
+        self.expect(";")?;
+        Ok(statement)
+    }
+
+    /// `if` statement, after the keyword
+    fn if_statement(&mut self) -> Result<Statement, LangError> {
+        let cond = self.expr(0)?;
+        let body = self.block()?;
+        let otherwise = if !self.keyword("else") {
+            Vec::new()
+        } else if self.keyword("if") {
+            vec![self.if_statement()?]
+        } else {
+            self.block()?
+        };
+        Ok(Statement::If(cond, body, otherwise))
+    }
+
+    /// Expression with operators of precedence `level` or higher
+    fn expr(&mut self, level: usize) -> Result<Expr, LangError> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut lhs = self.expr(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol(symbol) if LEVELS[level].contains(symbol) => *symbol,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.expr(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, LangError> {
+        match self.next() {
+            (Token::Number(number), _) => Ok(Expr::Number(number)),
+            (Token::Ident(name), loc) => Ok(Expr::Var(name, loc)),
+            (Token::Symbol("!"), _) => Ok(Expr::Not(Box::new(self.unary()?))),
+            (Token::Symbol("("), _) => {
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            (_, loc) => Err(LangError::Expected(loc, "expression")),
+        }
+    }
+}
+
+/// Direct interpreter of the language
+struct Runner<'a> {
+    input: std::slice::Iter<'a, u8>,
+    output: Vec<u8>,
+    variables: HashMap<String, u8>,
+    /// Number of statements left to run
+    steps: u64,
+}
+
+impl Runner<'_> {
+    /// Run `statements`, returning false if the steps ran out
+    fn block(&mut self, statements: &[Statement]) -> bool {
+        statements.iter().all(|statement| self.statement(statement))
+    }
+
+    /// Count one step, returning false if the steps ran out
+    fn step(&mut self) -> bool {
+        if self.steps == 0 {
+            return false;
+        }
+        self.steps -= 1;
+        true
+    }
+
+    fn statement(&mut self, statement: &Statement) -> bool {
+        if !self.step() {
+            return false;
+        }
+
+        match statement {
+            Statement::Var(name, _, Some(value)) | Statement::Assign(name, _, value) => {
+                let value = self.eval(value);
+                self.variables.insert(name.clone(), value);
+            }
+            Statement::Var(name, _, None) => {
+                self.variables.insert(name.clone(), 0);
+            }
+            Statement::If(cond, body, otherwise) => {
+                return if self.eval(cond) != 0 {
+                    self.block(body)
+                } else {
+                    self.block(otherwise)
+                };
+            }
+            Statement::While(cond, body) => {
+                // every check of the condition is a step, so empty loops run out too
+                while self.eval(cond) != 0 {
+                    if !self.step() || !self.block(body) {
+                        return false;
+                    }
+                }
+            }
+            Statement::Print(value) => {
+                let value = self.eval(value);
+                self.output.push(value);
+            }
+            Statement::PrintStr(text) => self.output.extend_from_slice(text),
+            Statement::Read(name, _) => {
+                let value = self.input.next().copied().unwrap_or(0);
+                self.variables.insert(name.clone(), value);
+            }
+        }
+        true
+    }
+
+    fn eval(&self, expr: &Expr) -> u8 {
+        match expr {
+            Expr::Number(number) => *number,
+            // variables declared in a branch that never ran are still 0
+            Expr::Var(name, _) => self.variables.get(name).copied().unwrap_or(0),
+            Expr::Not(value) => (self.eval(value) == 0) as u8,
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.eval(lhs), self.eval(rhs));
+                match *op {
+                    "+" => lhs.wrapping_add(rhs),
+                    "-" => lhs.wrapping_sub(rhs),
+                    "*" => lhs.wrapping_mul(rhs),
+                    "/" => lhs.checked_div(rhs).unwrap_or(0),
+                    "%" => lhs.checked_rem(rhs).unwrap_or(lhs),
+                    "==" => (lhs == rhs) as u8,
+                    "!=" => (lhs != rhs) as u8,
+                    "<" => (lhs < rhs) as u8,
+                    ">" => (lhs > rhs) as u8,
+                    "<=" => (lhs <= rhs) as u8,
+                    ">=" => (lhs >= rhs) as u8,
+                    "&&" => (lhs != 0 && rhs != 0) as u8,
+                    "||" => (lhs != 0 || rhs != 0) as u8,
+                    _ => unreachable!("unknown operator {}", op),
+                }
+            }
+        }
+    }
+}
+
+/// Brainfuck code generator. Every cell position is absolute, and every loop starts and ends on
+/// the same cell, so the pointer position is always known. Free cells are always 0.
+struct Generator {
+    code: String,
+    /// Nesting depth of the statement being generated, for indentation
+    depth: usize,
+    ptr: usize,
+    /// Whether each cell holds a variable or a temporary value
+    used: Vec<bool>,
+    variables: HashMap<String, usize>,
+}
+
+impl Generator {
+    fn block(&mut self, statements: &[Statement]) -> Result<(), LangError> {
+        for statement in statements {
+            self.newline();
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    /// Start a new line at the current depth
+    fn newline(&mut self) {
+        if !self.code.is_empty() {
+            self.code.push('\n');
+        }
+        self.code.push_str(&"  ".repeat(self.depth));
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), LangError> {
+        match statement {
+            Statement::Var(name, loc, value) => {
+                if self.variables.contains_key(name) {
+                    return Err(LangError::RedefinedVariable(*loc, name.clone()));
+                }
+                let value = match value {
+                    Some(value) => Some(self.expr(value)?),
+                    None => None,
+                };
+
+                let cell = self.alloc();
+                self.variables.insert(name.clone(), cell);
+                // the declaration may run again in a loop
+                self.clear(cell);
+                if let Some(value) = value {
+                    self.move_to(value, cell);
+                    self.free(value);
+                }
+            }
+            Statement::Assign(name, loc, value) => {
+                let cell = self.variable(name, *loc)?;
+                let value = self.expr(value)?;
+                self.clear(cell);
+                self.move_to(value, cell);
+                self.free(value);
+            }
+            Statement::If(cond, body, otherwise) => {
+                let cond = self.expr(cond)?;
+                if otherwise.is_empty() {
+                    self.open(cond);
+                    self.nested(body)?;
+                    self.clear(cond);
+                    self.close(cond);
+                    self.free(cond);
+                    return Ok(());
+                }
+
+                let run_else = self.alloc();
+                self.add(run_else, 1);
+                self.open(cond);
+                self.nested(body)?;
+                self.add(run_else, 255);
+                self.clear(cond);
+                self.close(cond);
+
+                self.newline();
+                self.open(run_else);
+                self.nested(otherwise)?;
+                self.add(run_else, 255);
+                self.close(run_else);
+                self.free(run_else);
+                self.free(cond);
+            }
+            Statement::While(cond, body) => {
+                let test = self.alloc();
+                let value = self.expr(cond)?;
+                self.move_to(value, test);
+                self.free(value);
+
+                self.open(test);
+                self.nested(body)?;
+                let value = self.expr(cond)?;
+                self.clear(test);
+                self.move_to(value, test);
+                self.free(value);
+                self.close(test);
+                self.free(test);
+            }
+            Statement::Print(value) => {
+                let value = self.expr(value)?;
+                self.goto(value);
+                self.code.push('.');
+                self.clear(value);
+                self.free(value);
+            }
+            Statement::PrintStr(text) => {
+                let cell = self.alloc();
+                let mut value = 0u8;
+                for byte in text {
+                    self.add(cell, byte.wrapping_sub(value));
+                    self.code.push('.');
+                    value = *byte;
+                }
+                self.clear(cell);
+                self.free(cell);
+            }
+            Statement::Read(name, loc) => {
+                // clearing first, EOF gives 0 whether it leaves the cell as is or sets it to 0
+                let cell = self.variable(name, *loc)?;
+                self.clear(cell);
+                self.code.push(',');
+            }
+        }
+        Ok(())
+    }
+
+    /// Generate a block nested inside a loop, then start a new line for the code closing it
+    fn nested(&mut self, statements: &[Statement]) -> Result<(), LangError> {
+        self.depth += 1;
+        let result = self.block(statements);
+        self.depth -= 1;
+        self.newline();
+        result
+    }
+
+    fn variable(&self, name: &str, loc: Location) -> Result<usize, LangError> {
+        match self.variables.get(name) {
+            Some(cell) => Ok(*cell),
+            None => Err(LangError::UndefinedVariable(loc, String::from(name))),
+        }
+    }
+
+    /// Evaluate `expr` into a new temporary cell
+    fn expr(&mut self, expr: &Expr) -> Result<usize, LangError> {
+        let cell = match expr {
+            Expr::Number(number) => {
+                let cell = self.alloc();
+                self.add(cell, *number);
+                cell
+            }
+            Expr::Var(name, loc) => {
+                let var = self.variable(name, *loc)?;
+                let cell = self.alloc();
+                self.copy(var, cell);
+                cell
+            }
+            Expr::Not(value) => {
+                let value = self.expr(value)?;
+                self.not(value)
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.expr(lhs)?;
+                let rhs = self.expr(rhs)?;
+                self.binary(op, lhs, rhs)
+            }
+        };
+        Ok(cell)
+    }
+
+    /// Apply `op` to the temporary cells `lhs` and `rhs`, freeing them. Return the cell with the
+    /// result.
+    fn binary(&mut self, op: &str, lhs: usize, rhs: usize) -> usize {
+        match op {
+            "+" => {
+                self.move_to(rhs, lhs);
+                self.free(rhs);
+                lhs
+            }
+            "-" => {
+                self.open(rhs);
+                self.add(rhs, 255);
+                self.add(lhs, 255);
+                self.close(rhs);
+                self.free(rhs);
+                lhs
+            }
+            "*" => {
+                let product = self.alloc();
+                self.open(lhs);
+                self.add(lhs, 255);
+                self.copy(rhs, product);
+                self.close(lhs);
+                self.clear(rhs);
+                self.free(rhs);
+                self.free(lhs);
+                product
+            }
+            "/" | "%" => self.divide(op, lhs, rhs),
+            "==" | "!=" => {
+                // lhs - rhs is 0 only if they are equal
+                let diff = self.binary("-", lhs, rhs);
+                let different = self.truth(diff);
+                if op == "==" {
+                    self.not(different)
+                } else {
+                    different
+                }
+            }
+            "<" => self.less(lhs, rhs),
+            ">" => self.less(rhs, lhs),
+            "<=" => {
+                let greater = self.less(rhs, lhs);
+                self.not(greater)
+            }
+            ">=" => {
+                let less = self.less(lhs, rhs);
+                self.not(less)
+            }
+            "&&" => {
+                let both = self.alloc();
+                self.open(lhs);
+                self.clear(lhs);
+                self.open(rhs);
+                self.add(both, 1);
+                self.clear(rhs);
+                self.close(rhs);
+                self.close(lhs);
+                self.clear(rhs);
+                self.free(rhs);
+                self.free(lhs);
+                both
+            }
+            "||" => {
+                let lhs = self.truth(lhs);
+                let rhs = self.truth(rhs);
+                let sum = self.binary("+", lhs, rhs);
+                self.truth(sum)
+            }
+            _ => unreachable!("unknown operator {}", op),
+        }
+    }
+
+    /// 1 if `lhs` < `rhs`, otherwise 0. Both cells count down together, and `lhs` is less if it
+    /// reaches 0 first.
+    fn less(&mut self, lhs: usize, rhs: usize) -> usize {
+        let less = self.alloc();
+        self.open(rhs);
+
+        let lhs_zero = self.alloc();
+        let lhs_copy = self.alloc();
+        self.copy(lhs, lhs_copy);
+        let lhs_nonzero = self.truth(lhs_copy);
+        self.add(lhs_zero, 1);
+        self.open(lhs_nonzero);
+        self.add(lhs_zero, 255);
+        self.add(lhs_nonzero, 255);
+        self.close(lhs_nonzero);
+        self.free(lhs_nonzero);
+
+        let count_down = self.alloc();
+        self.add(count_down, 1);
+        self.open(lhs_zero);
+        self.add(less, 1);
+        self.clear(rhs);
+        self.add(count_down, 255);
+        self.add(lhs_zero, 255);
+        self.close(lhs_zero);
+        self.open(count_down);
+        self.add(lhs, 255);
+        self.add(rhs, 255);
+        self.add(count_down, 255);
+        self.close(count_down);
+        self.free(count_down);
+        self.free(lhs_zero);
+
+        self.close(rhs);
+        self.clear(lhs);
+        self.free(rhs);
+        self.free(lhs);
+        less
+    }
+
+    /// Quotient or remainder of `lhs` / `rhs`, by subtracting `rhs` while `lhs` >= `rhs`. Nothing
+    /// is subtracted when dividing by 0.
+    fn divide(&mut self, op: &str, lhs: usize, rhs: usize) -> usize {
+        let quotient = self.alloc();
+
+        let divisor = self.alloc();
+        self.copy(rhs, divisor);
+        self.open(divisor);
+        self.clear(divisor);
+
+        let fits = self.fits(lhs, rhs);
+        self.open(fits);
+        self.add(quotient, 1);
+        let subtracted = self.alloc();
+        self.copy(rhs, subtracted);
+        let lhs_left = self.binary("-", lhs, subtracted);
+        debug_assert_eq!(lhs_left, lhs);
+        self.clear(fits);
+        let again = self.fits(lhs, rhs);
+        self.move_to(again, fits);
+        self.free(again);
+        self.close(fits);
+        self.free(fits);
+
+        self.close(divisor);
+        self.free(divisor);
+
+        self.clear(rhs);
+        self.free(rhs);
+        if op == "/" {
+            self.clear(lhs);
+            self.free(lhs);
+            quotient
+        } else {
+            self.clear(quotient);
+            self.free(quotient);
+            lhs
+        }
+    }
+
+    /// 1 if `lhs` >= `rhs`, otherwise 0, keeping both cells
+    fn fits(&mut self, lhs: usize, rhs: usize) -> usize {
+        let lhs_copy = self.alloc();
+        self.copy(lhs, lhs_copy);
+        let rhs_copy = self.alloc();
+        self.copy(rhs, rhs_copy);
+        let less = self.less(lhs_copy, rhs_copy);
+        self.not(less)
+    }
+
+    /// 1 if `cell` is 0, otherwise 0, freeing `cell`
+    fn not(&mut self, cell: usize) -> usize {
+        let result = self.alloc();
+        self.add(result, 1);
+        self.open(cell);
+        self.add(result, 255);
+        self.clear(cell);
+        self.close(cell);
+        self.free(cell);
+        result
+    }
+
+    /// 1 if `cell` is not 0, otherwise 0, freeing `cell`
+    fn truth(&mut self, cell: usize) -> usize {
+        let result = self.alloc();
+        self.open(cell);
+        self.add(result, 1);
+        self.clear(cell);
+        self.close(cell);
+        self.free(cell);
+        result
+    }
+
+    /// Find a free cell
+    fn alloc(&mut self) -> usize {
+        match self.used.iter().position(|used| !used) {
+            Some(cell) => {
+                self.used[cell] = true;
+                cell
+            }
+            None => {
+                self.used.push(true);
+                self.used.len() - 1
+            }
+        }
+    }
+
+    /// Give back a cell, which must be 0
+    fn free(&mut self, cell: usize) {
+        self.used[cell] = false;
+    }
+
+    fn goto(&mut self, cell: usize) {
+        let ch = if cell > self.ptr { '>' } else { '<' };
+        for _ in 0..cell.abs_diff(self.ptr) {
+            self.code.push(ch);
+        }
+        self.ptr = cell;
+    }
+
+    /// Add `value` to `cell`, going down instead when shorter
+    fn add(&mut self, cell: usize, value: u8) {
+        self.goto(cell);
+        let (ch, count) = if value <= 128 {
+            ('+', value as usize)
+        } else {
+            ('-', 256 - value as usize)
+        };
+        for _ in 0..count {
+            self.code.push(ch);
+        }
+    }
+
+    fn open(&mut self, cell: usize) {
+        self.goto(cell);
+        self.code.push('[');
+    }
+
+    fn close(&mut self, cell: usize) {
+        self.goto(cell);
+        self.code.push(']');
+    }
+
+    fn clear(&mut self, cell: usize) {
+        self.goto(cell);
+        self.code.push_str("[-]");
+    }
+
+    /// Add `src` to `dst`, leaving `src` at 0
+    fn move_to(&mut self, src: usize, dst: usize) {
+        self.open(src);
+        self.add(src, 255);
+        self.add(dst, 1);
+        self.close(src);
+    }
+
+    /// Add `src` to `dst`, keeping `src`
+    fn copy(&mut self, src: usize, dst: usize) {
+        let tmp = self.alloc();
+        self.open(src);
+        self.add(src, 255);
+        self.add(dst, 1);
+        self.add(tmp, 1);
+        self.close(src);
+        self.move_to(tmp, src);
+        self.free(tmp);
+    }
+}
@@ -1,14 +1,60 @@
+pub use crate::ast::*;
+pub use crate::bytecode::*;
+pub use crate::cfg::*;
+pub use crate::compiler::*;
+pub use crate::coverage::*;
+pub use crate::equivalence::*;
 pub use crate::error::*;
+pub use crate::explain::*;
+pub use crate::explorer::*;
+pub use crate::formatter::*;
+pub use crate::generator::*;
 pub use crate::interpreter::*;
 pub use crate::interpreter_builder::*;
+pub use crate::lang::*;
+pub use crate::lint::*;
+pub use crate::location::*;
+pub use crate::loop_detector::*;
+pub use crate::minifier::*;
+pub use crate::observer::*;
+pub use crate::optimizer::*;
+pub use crate::partial::*;
+pub use crate::preprocessor::*;
+pub use crate::profiler::*;
+pub use crate::program::*;
+pub use crate::selftest::*;
 pub use crate::settings::*;
+pub use crate::spec::*;
+pub use crate::trace::*;
 
+pub mod ast;
+pub mod bytecode;
+pub mod cfg;
+pub mod compiler;
+pub mod coverage;
+pub mod equivalence;
 pub mod error;
+pub mod explain;
+pub mod explorer;
+pub mod formatter;
+pub mod generator;
 pub mod interpreter;
 pub mod interpreter_builder;
+pub mod lang;
+pub mod lint;
+pub mod location;
+pub mod loop_detector;
+pub mod minifier;
+pub mod observer;
+pub mod optimizer;
+pub mod partial;
+pub mod preprocessor;
+pub mod profiler;
+pub mod program;
+pub mod selftest;
 pub mod settings;
+pub mod spec;
+pub mod trace;
 
 mod cells;
-mod compiler;
-mod program;
 mod stdio_wrapper;
@@ -0,0 +1,219 @@
+//! Static analysis of programs, warning about code that is most likely a mistake.
+//!
+//! The linter assumes the array starts with all zeros.
+
+use std::fmt;
+
+use crate::ast::*;
+use crate::location::*;
+use crate::program::*;
+
+/// Kind of lint warning
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WarningKind {
+    /// Two adjacent commands cancel each other, e.g. `+-` or `<>`
+    CancellingPair(char, char),
+    /// Loop is reached when the current cell is always 0, so its body never runs
+    DeadLoop,
+    /// Loop body never changes the current cell, so the loop runs forever once entered
+    InfiniteLoop,
+    /// Loop body moves the pointer by the given number of cells on every iteration
+    DriftingLoop(isize),
+    /// Code after a loop that always runs forever
+    Unreachable,
+    /// ',' whose cell is neither cleared before nor tested right after, so EOF goes unnoticed
+    UnguardedRead,
+}
+
+/// Lint warning
+#[derive(Clone, Debug)]
+pub struct Warning {
+    pub location: Location,
+    pub kind: WarningKind,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WarningKind::CancellingPair(first, second) => {
+                write!(f, "'{}{}' cancel each other", first, second)
+            }
+            WarningKind::DeadLoop => write!(f, "loop never runs, the cell is always 0 here"),
+            WarningKind::InfiniteLoop => {
+                write!(
+                    f,
+                    "loop never changes its cell, it runs forever once entered"
+                )
+            }
+            WarningKind::DriftingLoop(offset) => {
+                write!(f, "loop moves the pointer by {} every iteration", offset)
+            }
+            WarningKind::Unreachable => write!(f, "unreachable code after an infinite loop"),
+            WarningKind::UnguardedRead => write!(
+                f,
+                "',' is not guarded against EOF, clear the cell before or test it right after"
+            ),
+        }
+    }
+}
+
+/// Lint `program`, returning warnings ordered by location.
+pub fn lint(program: &Program) -> Vec<Warning> {
+    let mut linter = Linter {
+        warnings: Vec::new(),
+        known: Some(0),
+        tape_zero: true,
+        unreachable: false,
+        started: false,
+    };
+    linter.block(&Block::from(program), false);
+
+    linter.warnings.sort_by_key(|warning| warning.location);
+    linter.warnings
+}
+
+struct Linter {
+    warnings: Vec<Warning>,
+    /// Value of the current cell, if known
+    known: Option<u8>,
+    /// Whether every cell is still 0
+    tape_zero: bool,
+    /// Whether the code being linted can't be reached. Only the first unreachable node is
+    /// reported.
+    unreachable: bool,
+    /// Whether any command has run yet. Loops before that never run either, but are the usual
+    /// way to write a comment block at the start of a program, so they are not reported.
+    started: bool,
+}
+
+impl Linter {
+    fn warn(&mut self, location: Location, kind: WarningKind) {
+        self.warnings.push(Warning { location, kind });
+    }
+
+    /// Lint the nodes of `block`. `in_loop` is whether the block is a loop body, whose ']' tests
+    /// the current cell.
+    fn block(&mut self, block: &Block, in_loop: bool) {
+        let nodes = &block.0;
+        let mut skip_pair = false;
+
+        for (i, node) in nodes.iter().enumerate() {
+            if self.unreachable {
+                self.warn(node.location(), WarningKind::Unreachable);
+                self.unreachable = false;
+                // keep going with nothing known, so later code is still linted
+                self.known = None;
+                self.tape_zero = false;
+            }
+
+            match (node, nodes.get(i + 1)) {
+                _ if skip_pair => skip_pair = false,
+                (Node::Inc(loc), Some(Node::Dec(_))) => {
+                    self.warn(*loc, WarningKind::CancellingPair('+', '-'));
+                    skip_pair = true;
+                }
+                (Node::Dec(loc), Some(Node::Inc(_))) => {
+                    self.warn(*loc, WarningKind::CancellingPair('-', '+'));
+                    skip_pair = true;
+                }
+                (Node::Right(loc), Some(Node::Left(_))) => {
+                    self.warn(*loc, WarningKind::CancellingPair('>', '<'));
+                    skip_pair = true;
+                }
+                (Node::Left(loc), Some(Node::Right(_))) => {
+                    self.warn(*loc, WarningKind::CancellingPair('<', '>'));
+                    skip_pair = true;
+                }
+                _ => {}
+            }
+
+            if !matches!(node, Node::Loop(..)) {
+                self.started = true;
+            }
+            match node {
+                Node::Right(_) | Node::Left(_) => {
+                    if !self.tape_zero {
+                        self.known = None;
+                    }
+                }
+                Node::Inc(_) => {
+                    self.known = self.known.map(|val| val.wrapping_add(1));
+                    self.tape_zero = false;
+                }
+                Node::Dec(_) => {
+                    self.known = self.known.map(|val| val.wrapping_sub(1));
+                    self.tape_zero = false;
+                }
+                Node::Read(loc) => {
+                    let tested = match nodes.get(i + 1) {
+                        Some(next) => matches!(next, Node::Loop(..)),
+                        None => in_loop,
+                    };
+                    // a cell that is 0 only because nothing ran yet was never cleared
+                    let cleared = self.known == Some(0) && !self.tape_zero;
+                    if !cleared && !tested {
+                        self.warn(*loc, WarningKind::UnguardedRead);
+                    }
+                    self.known = None;
+                    self.tape_zero = false;
+                }
+                Node::Write(_) => {}
+                Node::Loop(body, open, _) => self.body(body, *open),
+            }
+        }
+    }
+
+    fn body(&mut self, body: &Block, open: Location) {
+        // the body never runs, so it is not linted and nothing changes
+        if self.known == Some(0) {
+            if self.started {
+                self.warn(open, WarningKind::DeadLoop);
+            }
+            return;
+        }
+        self.started = true;
+
+        let invariant = !modifies(body) && offset(body) == Some(0);
+        if invariant {
+            self.warn(open, WarningKind::InfiniteLoop);
+            if self.known.is_some() {
+                // the cell is not 0, so the loop is entered and never exits
+                self.block(body, true);
+                self.unreachable = true;
+                return;
+            }
+        } else if let Some(offset) = offset(body).filter(|offset| *offset != 0) {
+            self.warn(open, WarningKind::DriftingLoop(offset));
+        }
+
+        self.known = None;
+        self.block(body, true);
+        self.unreachable = false;
+
+        // the loop only exits when the current cell is 0
+        self.known = Some(0);
+    }
+}
+
+/// Whether `block` changes the value of any cell
+fn modifies(block: &Block) -> bool {
+    block.0.iter().any(|node| match node {
+        Node::Inc(_) | Node::Dec(_) | Node::Read(_) => true,
+        Node::Loop(body, _, _) => modifies(body),
+        _ => false,
+    })
+}
+
+/// Net pointer movement of `block`, if it does not depend on the cells
+fn offset(block: &Block) -> Option<isize> {
+    let mut net = 0;
+    for node in &block.0 {
+        match node {
+            Node::Right(_) => net += 1,
+            Node::Left(_) => net -= 1,
+            Node::Loop(body, _, _) if offset(body)? != 0 => return None,
+            _ => {}
+        }
+    }
+    Some(net)
+}
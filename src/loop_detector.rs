@@ -0,0 +1,105 @@
+//! Infinite-loop detection. Between two I/O commands a program runs deterministically, so once its
+//! state at a loop head repeats, it will repeat forever.
+//!
+//! States at loop heads are compared with Brent's cycle detection: a single snapshot is kept and
+//! replaced after 1, 2, 4, 8... loop heads, so memory stays bounded by the size of one snapshot.
+//! A snapshot only holds the cells visited since the last I/O, since the others can't have changed.
+
+use crate::cells::*;
+use crate::program::*;
+
+/// State at a loop head
+struct Snapshot {
+    command_ptr: usize,
+    cell_ptr: usize,
+    /// Length of the array, which grows with `--dynamic_size`
+    len: usize,
+    /// First and last visited cells
+    range: (usize, usize),
+    cells: Vec<u8>,
+}
+
+/// Detector of programs repeating the same state without I/O
+pub struct LoopDetector {
+    /// First and last cells visited since the last I/O
+    range: Option<(usize, usize)>,
+    snapshot: Option<Snapshot>,
+    /// Loop heads since the snapshot was taken
+    heads: u64,
+    /// Loop heads before the snapshot is replaced
+    power: u64,
+}
+
+impl LoopDetector {
+    pub fn new() -> Self {
+        Self {
+            range: None,
+            snapshot: None,
+            heads: 0,
+            power: 1,
+        }
+    }
+
+    /// Check the state after `command` ran. Return whether the program is in an infinite loop.
+    pub(crate) fn detect(
+        &mut self,
+        command: Command,
+        command_ptr: usize,
+        cells: &Cells,
+        cell_ptr: usize,
+    ) -> bool {
+        if matches!(command, Command::Read | Command::Write) {
+            *self = Self::new();
+        }
+        self.visit(cell_ptr, cells.0.len());
+
+        // only jumping back is a loop head
+        match command {
+            Command::Close(open) if command_ptr == open + 1 => {}
+            _ => return false,
+        }
+
+        let (start, end) = self.range.unwrap();
+        if let Some(snapshot) = &self.snapshot {
+            if snapshot.command_ptr == command_ptr
+                && snapshot.cell_ptr == cell_ptr
+                && snapshot.len == cells.0.len()
+                && snapshot.range == (start, end)
+                && snapshot.cells[..] == cells.0[start..=end]
+            {
+                return true;
+            }
+        }
+
+        self.heads += 1;
+        if self.heads == self.power {
+            self.snapshot = Some(Snapshot {
+                command_ptr,
+                cell_ptr,
+                len: cells.0.len(),
+                range: (start, end),
+                cells: cells.0[start..=end].to_vec(),
+            });
+            self.heads = 0;
+            self.power *= 2;
+        }
+        false
+    }
+
+    /// Record that the cell at `cell_ptr` was visited, if it exists.
+    fn visit(&mut self, cell_ptr: usize, len: usize) {
+        if cell_ptr >= len {
+            return;
+        }
+        self.range = match self.range {
+            Some((start, end)) => Some((start.min(cell_ptr), end.max(cell_ptr))),
+            None => Some((cell_ptr, cell_ptr)),
+        };
+    }
+}
+
+impl Default for LoopDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
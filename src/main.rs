@@ -1,9 +1,10 @@
-use std::fs::File;
-use std::io::prelude::*;
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
 
 use brainrust::*;
 
-use clap::{App, Arg, ArgMatches};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
 /// Error while parsing command-line arguments
 enum ParseError {
@@ -11,59 +12,465 @@ enum ParseError {
     EofBehavior(String),
     InputMode(String),
     NewlineMode(String),
+    Comment(String),
+    InputHex(String),
+    TapeInit(String),
+    TapeInitFile(String),
+    TraceFormat(String),
 }
 
-/// Read file and return its contents
-fn read_file(file_name: &str) -> std::io::Result<String> {
-    let mut file = File::open(file_name)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    Ok(contents)
+/// Parse a string of hexadecimal bytes, e.g. "48 65 6c" or "48656c". Whitespace is ignored.
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = hex.bytes().filter(|ch| !ch.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+/// Parsing command-line arguments into the input bytes, if input is not read from a file or stdin
+fn input(args: &ArgMatches) -> Result<Option<Vec<u8>>, ParseError> {
+    if let Some(input) = args.value_of("input_string") {
+        return Ok(Some(input.as_bytes().to_vec()));
+    }
+
+    match args.value_of("input_hex") {
+        Some(hex) => match parse_hex(hex) {
+            Some(input) => Ok(Some(input)),
+            None => Err(ParseError::InputHex(String::from(hex))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Parsing command-line arguments into the initial tape, given as hexadecimal bytes or read from
+/// a file
+fn tape(args: &ArgMatches) -> Result<Option<Vec<u8>>, ParseError> {
+    if let Some(path) = args.value_of("tape_init_file") {
+        return match std::fs::read(path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(_) => Err(ParseError::TapeInitFile(String::from(path))),
+        };
+    }
+
+    match args.value_of("tape_init") {
+        Some(hex) => match parse_hex(hex) {
+            Some(contents) => Ok(Some(contents)),
+            None => Err(ParseError::TapeInit(String::from(hex))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Parsing command-line arguments into the trace format
+fn trace_format(args: &ArgMatches) -> Result<TraceFormat, ParseError> {
+    match args.value_of("trace_format").unwrap_or("json") {
+        "json" => Ok(TraceFormat::JsonLines),
+        "binary" => Ok(TraceFormat::Binary),
+        trace_format => Err(ParseError::TraceFormat(String::from(trace_format))),
+    }
 }
 
-/// Parsing command-line arguments into settings
-fn settings<'a>(args: &ArgMatches<'a>) -> Result<Settings, ParseError> {
-    let array_size = args.value_of("array_size").unwrap_or(&"30000");
-    let array_size = match array_size.parse::<usize>() {
-        Ok(val) => val,
-        Err(_) => return Err(ParseError::ArraySize(String::from(array_size))),
+/// Parsing command-line arguments into the comment delimiter, if strict syntax is enabled
+fn comment(args: &ArgMatches) -> Result<Option<char>, ParseError> {
+    if !args.is_present("strict") {
+        return Ok(None);
+    }
+
+    let comment = args.value_of("comment").unwrap_or("#");
+    let mut chars = comment.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) if !"><+-[],.".contains(ch) => Ok(Some(ch)),
+        _ => Err(ParseError::Comment(String::from(comment))),
+    }
+}
+
+/// Parsing command-line arguments into settings. Values not given on the command-line are
+/// taken from `defaults`.
+fn settings(args: &ArgMatches, defaults: &Settings) -> Result<Settings, ParseError> {
+    let array_size = match args.value_of("array_size") {
+        Some(array_size) => match array_size.parse::<usize>() {
+            Ok(val) => val,
+            Err(_) => return Err(ParseError::ArraySize(String::from(array_size))),
+        },
+        None => defaults.array_size,
     };
 
-    let eof_behavior = args.value_of("eof_behavior").unwrap_or(&"as_is");
-    let eof_behavior = match eof_behavior {
-        "as_is" => EofBehavior::AsIs,
-        "negative_one" => EofBehavior::NegativeOne,
-        "zero" => EofBehavior::Zero,
-        _ => return Err(ParseError::EofBehavior(String::from(eof_behavior))),
+    let eof_behavior = match args.value_of("eof_behavior") {
+        Some("as_is") => EofBehavior::AsIs,
+        Some("negative_one") => EofBehavior::NegativeOne,
+        Some("zero") => EofBehavior::Zero,
+        Some(eof_behavior) => return Err(ParseError::EofBehavior(String::from(eof_behavior))),
+        None => defaults.eof_behavior.clone(),
     };
 
-    let newline_mode = args.value_of("newline").unwrap_or(&"CRLF");
-    let newline_mode = match newline_mode {
-        "CRLF" => NewlineMode::CRLF,
-        "LF" => NewlineMode::LF,
-        _ => return Err(ParseError::NewlineMode(String::from(newline_mode))),
+    let newline_mode = match args.value_of("newline_mode") {
+        Some("CRLF") => NewlineMode::CRLF,
+        Some("LF") => NewlineMode::LF,
+        Some(newline_mode) => return Err(ParseError::NewlineMode(String::from(newline_mode))),
+        None => defaults.newline_mode.clone(),
     };
 
-    let input_mode = args.value_of("input_mode").unwrap_or(&"ascii");
-    let input_mode = match input_mode {
-        "ascii" => InputMode::Ascii,
-        "digit" => InputMode::Digit,
-        _ => return Err(ParseError::InputMode(String::from(input_mode))),
+    let input_mode = match args.value_of("input_mode") {
+        Some("ascii") => InputMode::Ascii,
+        Some("digit") => InputMode::Digit,
+        Some(input_mode) => return Err(ParseError::InputMode(String::from(input_mode))),
+        None => defaults.input_mode.clone(),
     };
 
     Ok(Settings {
-        dynamic_size: args.is_present("dynamic_size"),
+        dynamic_size: flag(args, "dynamic_size", "fixed_size", defaults.dynamic_size),
         array_size,
         eof_behavior,
         newline_mode,
-        ignore_newline: args.is_present("ignore_newline") || !args.is_present("input"),
+        ignore_newline: args.is_present("ignore_newline")
+            || !(args.is_present("input")
+                || args.is_present("input_string")
+                || args.is_present("input_hex")),
         input_mode,
-        wrapping: args.is_present("wrapping"),
+        wrapping: flag(args, "wrapping", "no_wrapping", defaults.wrapping),
     })
 }
 
+/// Value of a flag that can be turned on with `on` and off with `off`, or `default` if neither is
+/// given
+fn flag(args: &ArgMatches, on: &str, off: &str, default: bool) -> bool {
+    if args.is_present(on) {
+        true
+    } else if args.is_present(off) {
+        false
+    } else {
+        default
+    }
+}
+
+/// Parse the value of the `name` option into `val`, if it is given. Return false after printing
+/// an error if it cannot be parsed.
+fn number<T: std::str::FromStr>(args: &ArgMatches, name: &str, val: &mut T) -> bool {
+    if let Some(number) = args.value_of(name) {
+        match number.parse() {
+            Ok(number) => *val = number,
+            Err(_) => {
+                eprintln!("Failed to parse {}: \"{}\"", name, number);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Print error while parsing command-line arguments
+fn report_parse_error(err: ParseError) {
+    match err {
+        ParseError::ArraySize(err) => eprintln!("Failed to parse array_size: \"{}\"", err),
+        ParseError::EofBehavior(err) => eprintln!("Failed to parse eof_behavior: \"{}\"", err),
+        ParseError::NewlineMode(err) => eprintln!("Failed to parse newline_mode: \"{}\"", err),
+        ParseError::InputMode(err) => eprintln!("Failed to parse input_mode: \"{}\"", err),
+        ParseError::Comment(err) => eprintln!("Failed to parse comment: \"{}\"", err),
+        ParseError::InputHex(err) => eprintln!("Failed to parse input_hex: \"{}\"", err),
+        ParseError::TapeInit(err) => eprintln!("Failed to parse tape_init: \"{}\"", err),
+        ParseError::TapeInitFile(err) => eprintln!("Failed to read tape_init_file: \"{}\"", err),
+        ParseError::TraceFormat(err) => eprintln!("Failed to parse trace_format: \"{}\"", err),
+    }
+}
+
+/// Location in the original file, if the source was preprocessed
+fn locate(loc: Location, map: Option<&SourceMap>) -> String {
+    match map.and_then(|map| map.lookup(loc)) {
+        Some((path, loc)) => format!("{}:{}", path.display(), loc),
+        None => loc.to_string(),
+    }
+}
+
+/// Print errors while compiling
+fn report_compile_errors(errors: Vec<CompileError>, map: Option<&SourceMap>) {
+    for err in errors {
+        match err {
+            CompileError::Syntax(loc, ch) => {
+                eprintln!("Invalid syntax at {}: '{}'", locate(loc, map), ch)
+            }
+            CompileError::UnmatchedOpen(loc, end) => eprintln!(
+                "Cannot find matching ']' for '[' at {} (loop body probably ends at {})",
+                locate(loc, map),
+                locate(end, map)
+            ),
+            CompileError::UnmatchedClose(loc) => {
+                eprintln!("Cannot find matching '[' for ']' at {}", locate(loc, map))
+            }
+        }
+    }
+}
+
+/// Print error while preprocessing
+fn report_preprocess_error(err: PreprocessError) {
+    match err {
+        PreprocessError::Io(path, err) => eprintln!("Failed to read {}: {}", path.display(), err),
+        PreprocessError::InvalidDefine(path, loc) => {
+            eprintln!("Invalid #define at {}:{}", path.display(), loc)
+        }
+        PreprocessError::Redefined(path, loc, name) => {
+            eprintln!(
+                "Macro defined again at {}:{}: {}",
+                path.display(),
+                loc,
+                name
+            )
+        }
+        PreprocessError::InvalidInclude(path, loc) => {
+            eprintln!("Invalid #include at {}:{}", path.display(), loc)
+        }
+        PreprocessError::ArgumentCount(path, loc, name, count) => eprintln!(
+            "Macro {} at {}:{} takes {} argument(s) in parentheses",
+            name,
+            path.display(),
+            loc,
+            count
+        ),
+        PreprocessError::RecursiveMacro(path, loc, name) => {
+            eprintln!(
+                "Macro expands to itself at {}:{}: {}",
+                path.display(),
+                loc,
+                name
+            )
+        }
+        PreprocessError::InvalidRepeat(path, loc) => eprintln!(
+            "Invalid repeat at {}:{}, expected {{code}}*count",
+            path.display(),
+            loc
+        ),
+        PreprocessError::RepeatTooLarge(path, loc) => eprintln!(
+            "Repeat at {}:{} expands to more than {} characters",
+            path.display(),
+            loc,
+            MAX_REPEAT
+        ),
+        PreprocessError::ExpansionTooLarge(path, loc) => eprintln!(
+            "Expansion at {}:{} reaches the limit of {} characters for all macros and repeats",
+            path.display(),
+            loc,
+            MAX_EXPANSION
+        ),
+    }
+}
+
+/// Print error while compiling the high-level language
+fn report_lang_error(err: LangError) {
+    match err {
+        LangError::InvalidChar(loc, ch) => eprintln!("Invalid character at {}: '{}'", loc, ch),
+        LangError::InvalidNumber(loc, number) => {
+            eprintln!("Number out of range at {}: {}", loc, number)
+        }
+        LangError::InvalidLiteral(loc) => eprintln!("Invalid string or character at {}", loc),
+        LangError::Expected(loc, expected) => eprintln!("Expected {} at {}", expected, loc),
+        LangError::UndefinedVariable(loc, name) => {
+            eprintln!("Undefined variable at {}: {}", loc, name)
+        }
+        LangError::RedefinedVariable(loc, name) => {
+            eprintln!("Variable declared again at {}: {}", loc, name)
+        }
+    }
+}
+
+/// Print error while encoding or decoding bytecode
+fn report_bytecode_error(err: BytecodeError) {
+    match err {
+        BytecodeError::InvalidMagic => eprintln!("Not a bytecode file"),
+        BytecodeError::UnsupportedVersion(version) => {
+            eprintln!("Unsupported bytecode version: {}", version)
+        }
+        BytecodeError::Truncated => eprintln!("Bytecode is truncated"),
+        BytecodeError::TrailingData => eprintln!("Bytecode has trailing data"),
+        BytecodeError::ChecksumMismatch => eprintln!("Bytecode checksum does not match"),
+        BytecodeError::InvalidSettings => eprintln!("Bytecode has invalid settings"),
+        BytecodeError::InvalidCommand(idx) => eprintln!("Invalid bytecode op at {}", idx),
+        BytecodeError::InvalidJump(idx) => eprintln!("Invalid bytecode jump at op {}", idx),
+        BytecodeError::MissingHalt => eprintln!("Bytecode does not end with halt"),
+        BytecodeError::TooLarge => eprintln!("Program is too large for bytecode"),
+    }
+}
+
+/// Load the program in the "source" argument, which is either source code or bytecode, together
+/// with the settings to run it with. Settings stored in bytecode are used unless they are given on
+/// the command-line. Errors are printed and `None` is returned.
+fn load(args: &ArgMatches) -> std::io::Result<Option<(Program, Settings)>> {
+    load_source(args, args.value_of("source").unwrap())
+}
+
+/// Load the program at `path` like `load`, with the settings given in `args`
+fn load_source(args: &ArgMatches, path: &str) -> std::io::Result<Option<(Program, Settings)>> {
+    let contents = std::fs::read(path)?;
+
+    let (program, defaults) = if bytecode::is_bytecode(&contents) {
+        match bytecode::decode(&contents) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                report_bytecode_error(err);
+                return Ok(None);
+            }
+        }
+    } else {
+        let comment = match comment(args) {
+            Ok(comment) => comment,
+            Err(err) => {
+                report_parse_error(err);
+                return Ok(None);
+            }
+        };
+
+        let src = String::from_utf8_lossy(&contents).into_owned();
+        let (src, map) = if args.is_present("preprocess") {
+            let source = std::path::Path::new(path);
+            match Preprocessor::new().preprocess(&src, source) {
+                Ok((src, map)) => (src, Some(map)),
+                Err(err) => {
+                    report_preprocess_error(err);
+                    return Ok(None);
+                }
+            }
+        } else {
+            (src, None)
+        };
+
+        let compiler = match comment {
+            Some(comment) => Compiler::new().strict(comment),
+            None => Compiler::new(),
+        };
+        match compiler.compile(src) {
+            Ok(program) => (program, Settings::default()),
+            Err(errors) => {
+                report_compile_errors(errors, map.as_ref());
+                return Ok(None);
+            }
+        }
+    };
+
+    match settings(args, &defaults) {
+        Ok(settings) => Ok(Some((program, settings))),
+        Err(err) => {
+            report_parse_error(err);
+            Ok(None)
+        }
+    }
+}
+
+/// Source code the program in the "source" argument was compiled from, preprocessed if
+/// `--preprocess` is given, with its source map. `None` is returned for bytecode.
+fn compiled_source(args: &ArgMatches) -> std::io::Result<Option<(String, Option<SourceMap>)>> {
+    let path = args.value_of("source").unwrap();
+    let contents = std::fs::read(path)?;
+    if bytecode::is_bytecode(&contents) {
+        return Ok(None);
+    }
+
+    let src = String::from_utf8_lossy(&contents).into_owned();
+    if !args.is_present("preprocess") {
+        return Ok(Some((src, None)));
+    }
+    // errors were already reported while loading the program
+    let preprocessed = Preprocessor::new().preprocess(&src, std::path::Path::new(path));
+    Ok(preprocessed.ok().map(|(src, map)| (src, Some(map))))
+}
+
+/// Arguments for settings that are shared by running and compiling
+fn settings_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("dynamic_size")
+            .short("d")
+            .long("dynamic_size")
+            .help("Use dynamic size instead of fixed size array. If this flag is used, --array_size will specify the initial size."),
+        Arg::with_name("fixed_size")
+            .long("fixed_size")
+            .conflicts_with("dynamic_size")
+            .help("Use fixed size array, even if the bytecode was compiled with --dynamic_size."),
+        Arg::with_name("array_size")
+            .short("s")
+            .long("array_size")
+            .takes_value(true)
+            .value_name("SIZE")
+            .help("Size of array. Default: 30000."),
+        Arg::with_name("eof_behavior")
+            .long("eof_behavior")
+            .takes_value(true)
+            .value_name("EOF_BEHAVIOR")
+            .help(
+                "\
+                Behavior when received EOF as input:
+                    as_is [Default] -- leave the current cell untouched.
+                    zero -- set the current cell value to 0.
+                    negative_one -- set the current cell value to -1.
+                ",
+            ),
+        Arg::with_name("newline_mode")
+            .long("newline_mode")
+            .takes_value(true)
+            .value_name("NEWLINE_MODE")
+            .help("Select newline mode: CRLF or LF. Default: CRLF"),
+        Arg::with_name("input_mode")
+            .long("input_mode")
+            .takes_value(true)
+            .value_name("INPUT_MODE")
+            .help(
+                "\
+                Select input mode:
+                    ascii [Default] -- no input conversion.
+                    digit -- convert input into digit (between 0 ... 255)
+                ",
+            ),
+        Arg::with_name("strict")
+            .long("strict")
+            .help("Reject every character that is not a command, whitespace or inside a line comment."),
+        Arg::with_name("comment")
+            .long("comment")
+            .takes_value(true)
+            .value_name("CHAR")
+            .requires("strict")
+            .help("Character that starts a line comment in strict mode. Default: #."),
+        Arg::with_name("preprocess")
+            .long("preprocess")
+            .help("Expand #include, #define macros and {code}*count repeats before compiling."),
+        Arg::with_name("wrapping")
+            .long("wrapping")
+            .short("w")
+            .help("Wrapping '>' and '<'. \"--dynamic_size\" will override this flag."),
+        Arg::with_name("no_wrapping")
+            .long("no_wrapping")
+            .conflicts_with("wrapping")
+            .help("No wrapping, even if the bytecode was compiled with --wrapping."),
+    ]
+}
+
+/// Compile the program into bytecode
+fn compile(args: &ArgMatches) -> std::io::Result<()> {
+    let (program, settings) = match load(args)? {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+
+    let source = args.value_of("source").unwrap();
+    let output = match args.value_of("output") {
+        Some(output) => std::path::PathBuf::from(output),
+        None => std::path::Path::new(source).with_extension("bfc"),
+    };
+
+    match bytecode::encode(&program, &settings) {
+        Ok(bytes) => std::fs::write(output, bytes),
+        Err(err) => {
+            report_bytecode_error(err);
+            Ok(())
+        }
+    }
+}
+
 fn main() -> std::io::Result<()> {
-    let app = App::new("brainfuck_rs")
+    let args = App::new("brainfuck_rs")
         .author("Luan N.")
         .version("0.1")
         .about("An interpreter written in Rust.")
@@ -78,101 +485,1209 @@ fn main() -> std::io::Result<()> {
                 .takes_value(true)
                 .value_name("INPUT")
                 .help("Specify which file to read input from. Default: stdin."),
+            Arg::with_name("input_string")
+                .long("input_string")
+                .takes_value(true)
+                .value_name("STRING")
+                .conflicts_with_all(&["input", "input_hex"])
+                .help("Use the given string as input instead of reading from a file or stdin."),
+            Arg::with_name("input_hex")
+                .long("input_hex")
+                .takes_value(true)
+                .value_name("HEX")
+                .conflicts_with_all(&["input", "input_string"])
+                .help("Use the given hexadecimal bytes (e.g. \"48 65 6c\") as input instead of reading from a file or stdin."),
             Arg::with_name("output")
                 .short("o")
                 .takes_value(true)
                 .value_name("OUTPUT")
                 .help("Specify which file to write output to. Default: stdout."),
-            Arg::with_name("dynamic_size")
-                .short("d")
-                .long("dynamic_size")
-                .help("Use dynamic size instead of fixed size array. If this flag is used, --array_size will specify the initial size."),
-            Arg::with_name("array_size")
-                .short("s")
-                .long("array_size")
+            Arg::with_name("tape_init")
+                .long("tape_init")
                 .takes_value(true)
-                .value_name("SIZE")
-                .help("Size of array. Default: 30000."),
-            Arg::with_name("final_array")
-                .long("final_array")
-                .help("Display final array after program finished."),
-            Arg::with_name("eof_behavior")
-                .long("eof_behavior")
+                .value_name("HEX")
+                .conflicts_with("tape_init_file")
+                .help("Initial values of the array, given as hexadecimal bytes (e.g. \"00 ff\"). The array is extended to fit a longer tape. Default: all zeros."),
+            Arg::with_name("tape_init_file")
+                .long("tape_init_file")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with("tape_init")
+                .help("Initial values of the array, read from a file. The array is extended to fit a longer tape. Default: all zeros."),
+            Arg::with_name("trace")
+                .long("trace")
                 .takes_value(true)
-                .value_name("EOF_BEHAVIOR")
+                .value_name("FILE")
+                .help("Record every executed command to the given file."),
+            Arg::with_name("trace_format")
+                .long("trace_format")
+                .takes_value(true)
+                .value_name("TRACE_FORMAT")
+                .requires("trace")
                 .help(
                     "\
-                    Behavior when received EOF as input:
-                        as_is [Default] -- leave the current cell untouched.
-                        zero -- set the current cell value to 0.
-                        negative_one -- set the current cell value to -1.
+                    Select trace format:
+                        json [Default] -- one JSON object per line.
+                        binary -- compact fixed-size records.
                     ",
                 ),
-            Arg::with_name("newline_mode")
-                .long("newline_mode")
+            Arg::with_name("profile")
+                .long("profile")
+                .help("Display how many commands ran and the hottest loops after program finished."),
+            Arg::with_name("profile_listing")
+                .long("profile_listing")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Write the source code annotated with how many commands ran on each line to the given file. With --preprocess, the preprocessed source code is annotated."),
+            Arg::with_name("profile_folded")
+                .long("profile_folded")
                 .takes_value(true)
-                .value_name("NEWLINE_MODE")
-                .help("Select newline mode: CRLF or LF. Default: CRLF"),
+                .value_name("FILE")
+                .help("Write the profile in folded stack format, with nested loops as frames, to the given file."),
+            Arg::with_name("detect_loops")
+                .long("detect_loops")
+                .help("Stop the program when it repeats a state at a loop head without any input or output in between."),
+            Arg::with_name("partial")
+                .long("partial")
+                .conflicts_with_all(&["trace", "profile", "profile_listing", "profile_folded"])
+                .help("Run the part of the program before its first input ahead of time, and start from the state it leaves."),
+            Arg::with_name("final_array")
+                .long("final_array")
+                .help("Display final array after program finished."),
             Arg::with_name("ignore_newline")
                 .long("ignore_newline")
                 .help("Ignore newline input character. Default to true if input is stdin, false otherwise."),
-            Arg::with_name("input_mode")
-                .long("input_mode")
-                .takes_value(true)
-                .value_name("INPUT_MODE")
-                .help(
-                    "\
-                    Select input mode:
-                        ascii [Default] -- no input conversion.
-                        digit -- convert input into digit (between 0 ... 255)
-                    ",
-                ),
-            Arg::with_name("wrapping")
-                .long("wrapping")
-                .short("w")
-                .help("Wrapping '>' and '<'. \"--dynamic_size\" will override this flag.")
-        ]);
-    let args = app.get_matches();
-
-    let settings = match settings(&args) {
-        Ok(settings) => settings,
-        Err(ParseError::ArraySize(err)) => {
-            eprintln!("Failed to parse array_size: \"{}\"", err);
+        ])
+        .args(&settings_args())
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("compile")
+                .about("Compile a program into bytecode, which can be run directly.")
+                .args(&[
+                    Arg::with_name("source")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SOURCE")
+                        .help("Source file"),
+                    Arg::with_name("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("OUTPUT")
+                        .help("Specify which file to write bytecode to. Default: SOURCE with .bfc extension."),
+                ])
+                .args(&settings_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("coverage")
+                .about("Run a program on every input file and report which commands and loop bodies ran.")
+                .args(&[
+                    Arg::with_name("source")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SOURCE")
+                        .help("Source file"),
+                    Arg::with_name("inputs")
+                        .multiple(true)
+                        .value_name("INPUTS")
+                        .help("Input files. Coverage is merged over all of them. Default: a single run with empty input."),
+                    Arg::with_name("lcov")
+                        .long("lcov")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Write coverage in lcov format to the given file."),
+                    Arg::with_name("annotate")
+                        .long("annotate")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Write the source code annotated with coverage to the given file. Lines that never ran are marked with #####. With --preprocess, the preprocessed source code is annotated."),
+                    Arg::with_name("max_steps")
+                        .long("max_steps")
+                        .takes_value(true)
+                        .value_name("STEPS")
+                        .help("Number of commands run on a single input before it is stopped and coverage is partial. Default: 10000000."),
+                    Arg::with_name("ignore_newline")
+                        .long("ignore_newline")
+                        .help("Ignore newline input character."),
+                ])
+                .args(&settings_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Warn about code that is most likely a mistake. Exit with status 1 if there is any warning.")
+                .arg(
+                    Arg::with_name("source")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SOURCE")
+                        .help("Source file"),
+                )
+                .args(&settings_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Format source files in place: indent by loop nesting, group runs of commands and wrap lines.")
+                .args(&[
+                    Arg::with_name("sources")
+                        .required(true)
+                        .multiple(true)
+                        .value_name("SOURCES")
+                        .help("Source files"),
+                    Arg::with_name("check")
+                        .long("check")
+                        .help("Only check that the files are formatted. Exit with status 1 if any is not."),
+                    Arg::with_name("width")
+                        .long("width")
+                        .takes_value(true)
+                        .value_name("WIDTH")
+                        .help("Maximum line width. Comments are never wrapped. Default: 80."),
+                    Arg::with_name("indent")
+                        .long("indent")
+                        .takes_value(true)
+                        .value_name("INDENT")
+                        .help("Number of spaces per loop nesting level. Default: 2."),
+                    Arg::with_name("strict")
+                        .long("strict")
+                        .help("Parse the files with strict syntax, so line comments are kept as they are."),
+                    Arg::with_name("comment")
+                        .long("comment")
+                        .takes_value(true)
+                        .value_name("CHAR")
+                        .requires("strict")
+                        .help("Character that starts a line comment in strict mode. Default: #."),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("minify")
+                .about("Strip comments, cancelling commands and loops that never run.")
+                .args(&[
+                    Arg::with_name("source")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SOURCE")
+                        .help("Source file"),
+                    Arg::with_name("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("OUTPUT")
+                        .help("Specify which file to write the minified code to. Default: stdout."),
+                    Arg::with_name("tape_init")
+                        .long("tape_init")
+                        .takes_value(true)
+                        .value_name("HEX")
+                        .conflicts_with("tape_init_file")
+                        .help("Initial values of the array the program is run with, given as hexadecimal bytes (e.g. \"00 ff\"). Default: all zeros."),
+                    Arg::with_name("tape_init_file")
+                        .long("tape_init_file")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .conflicts_with("tape_init")
+                        .help("Initial values of the array the program is run with, read from a file. Default: all zeros."),
+                ])
+                .args(&settings_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("gen-text")
+                .about("Generate a program printing the given text.")
+                .args(&[
+                    Arg::with_name("text")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("TEXT")
+                        .help("Text to print"),
+                    Arg::with_name("hex")
+                        .long("hex")
+                        .help("Read TEXT as hexadecimal bytes (e.g. \"48 65 6c\")."),
+                    Arg::with_name("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("OUTPUT")
+                        .help("Specify which file to write the program to. Default: stdout."),
+                    Arg::with_name("cells")
+                        .long("cells")
+                        .takes_value(true)
+                        .value_name("CELLS")
+                        .help("Maximum number of cells used. Default: 16."),
+                    Arg::with_name("non_wrapping")
+                        .long("non_wrapping")
+                        .help("Never overflow or underflow a cell, so the program also runs on interpreters without wrapping cells."),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("lang")
+                .about("Compile a program written in the high-level language (see `src/lang.rs`) into Brainfuck.")
+                .args(&[
+                    Arg::with_name("source")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SOURCE")
+                        .help("Source file"),
+                    Arg::with_name("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("OUTPUT")
+                        .help("Specify which file to write Brainfuck code to. Default: SOURCE with .bf extension."),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("explain")
+                .about("Print the program as pseudocode, with clears, moves and multiplications recognized.")
+                .arg(
+                    Arg::with_name("source")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SOURCE")
+                        .help("Source file"),
+                )
+                .args(&settings_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("cfg")
+                .about("Print the control-flow graph of the program: basic blocks between '[' and ']', and the edges between them.")
+                .args(&[
+                    Arg::with_name("source")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SOURCE")
+                        .help("Source file"),
+                    Arg::with_name("dot")
+                        .long("dot")
+                        .help("Write the graph in Graphviz DOT format, with loop back edges labeled by source span."),
+                    Arg::with_name("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("OUTPUT")
+                        .help("Specify which file to write the graph to. Default: stdout."),
+                ])
+                .args(&settings_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("explore")
+                .about("Run the program with symbolic input and generate inputs taking every edge of every loop, and inputs making it fail.")
+                .args(&[
+                    Arg::with_name("source")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("SOURCE")
+                        .help("Source file"),
+                    Arg::with_name("output")
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("DIR")
+                        .help("Write each generated input to a numbered file in the given directory."),
+                    Arg::with_name("max_steps")
+                        .long("max_steps")
+                        .takes_value(true)
+                        .value_name("STEPS")
+                        .help("Number of commands run over all paths before giving up. Default: 1000000."),
+                    Arg::with_name("ignore_newline")
+                        .long("ignore_newline")
+                        .help("Ignore newline input character."),
+                ])
+                .args(&settings_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("equiv")
+                .about("Run two programs on every input up to a length, and on random inputs, and report the first input they behave differently on.")
+                .args(&[
+                    Arg::with_name("source")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("A")
+                        .help("Source file of the first program"),
+                    Arg::with_name("other")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("B")
+                        .help("Source file of the second program"),
+                    Arg::with_name("max_length")
+                        .long("max_length")
+                        .takes_value(true)
+                        .value_name("LENGTH")
+                        .help("Check every input up to this length. Default: 2."),
+                    Arg::with_name("alphabet")
+                        .long("alphabet")
+                        .takes_value(true)
+                        .value_name("STRING")
+                        .help("Make inputs out of the characters of the given string. Default: every byte."),
+                    Arg::with_name("random")
+                        .long("random")
+                        .takes_value(true)
+                        .value_name("COUNT")
+                        .help("Also check this many random inputs. Default: 0."),
+                    Arg::with_name("random_length")
+                        .long("random_length")
+                        .takes_value(true)
+                        .value_name("LENGTH")
+                        .help("Maximum length of random inputs. Default: 64."),
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .takes_value(true)
+                        .value_name("SEED")
+                        .help("Seed of random inputs. Default: 1."),
+                    Arg::with_name("max_steps")
+                        .long("max_steps")
+                        .takes_value(true)
+                        .value_name("STEPS")
+                        .help("Number of commands run on a single input before giving up. Default: 1000000."),
+                    Arg::with_name("output_only")
+                        .long("output_only")
+                        .help("Only compare outputs and how the programs stop, not their final tapes."),
+                    Arg::with_name("ignore_newline")
+                        .long("ignore_newline")
+                        .help("Ignore newline input character."),
+                ])
+                .args(&settings_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Run the test cases of every program with a spec file next to it, in parallel, and report the failing ones.")
+                .args(&[
+                    Arg::with_name("paths")
+                        .multiple(true)
+                        .value_name("PATHS")
+                        .help("Programs, or directories searched for *.bf files with a *.toml spec next to them. Default: the current directory."),
+                    Arg::with_name("jobs")
+                        .short("j")
+                        .long("jobs")
+                        .takes_value(true)
+                        .value_name("JOBS")
+                        .help("Number of test cases run at the same time. Default: the number of CPUs."),
+                ]),
+        )
+        .subcommand(
+            SubCommand::with_name("selftest")
+                .about("Run classic portability test programs under combinations of settings, and check the interpreter does what the settings say."),
+        )
+        .get_matches();
+
+    match args.subcommand() {
+        ("compile", Some(args)) => compile(args),
+        ("coverage", Some(args)) => coverage(args),
+        ("lint", Some(args)) => lint(args),
+        ("fmt", Some(args)) => fmt(args),
+        ("minify", Some(args)) => minify(args),
+        ("gen-text", Some(args)) => gen_text(args),
+        ("lang", Some(args)) => lang(args),
+        ("explain", Some(args)) => explain(args),
+        ("cfg", Some(args)) => cfg(args),
+        ("explore", Some(args)) => explore(args),
+        ("equiv", Some(args)) => equiv(args),
+        ("test", Some(args)) => test(args),
+        ("selftest", Some(_)) => selftest(),
+        _ => run(&args),
+    }
+}
+
+/// Run the program on every input file and report which commands and loop bodies ran
+fn coverage(args: &ArgMatches) -> std::io::Result<()> {
+    let (program, mut settings) = match load(args)? {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+    settings.ignore_newline = args.is_present("ignore_newline");
+
+    let inputs: Vec<&str> = args
+        .values_of("inputs")
+        .map_or(Vec::new(), |inputs| inputs.collect());
+    let inputs = if inputs.is_empty() {
+        vec![None]
+    } else {
+        inputs.into_iter().map(Some).collect()
+    };
+
+    let mut max_steps = COVERAGE_STEPS;
+    if !number(args, "max_steps", &mut max_steps) {
+        return Ok(());
+    }
+
+    let coverage = Rc::new(RefCell::new(Coverage::new(&program)));
+    for input in inputs {
+        let mut builder = InterpreterBuilder::from_program(program.clone(), settings.clone())
+            .observer(Box::new(coverage.clone()));
+        builder = match input {
+            Some(input) => builder.input(std::fs::read(input)?),
+            None => builder.input(Vec::new()),
+        };
+
+        let mut interpreter = match builder.build() {
+            Ok(interpreter) => interpreter,
+            Err(errors) => {
+                report_compile_errors(errors, None);
+                return Ok(());
+            }
+        };
+        interpreter.writer = Box::new(std::io::sink());
+
+        let input = input.unwrap_or("<empty input>");
+        let mut state = interpreter.ready();
+        match interpreter.run_for(&mut state, max_steps) {
+            RunResult::Halted => {}
+            RunResult::None => eprintln!(
+                "{}: stopped after {} steps, coverage is partial",
+                input, max_steps
+            ),
+            result => eprintln!("{}: stopped with {:?}", input, result),
+        }
+    }
+
+    let coverage = coverage.borrow();
+    print!("{}", coverage.summary());
+
+    let source = args.value_of("source").unwrap();
+    let compiled = compiled_source(args)?;
+    if let Some(lcov) = args.value_of("lcov") {
+        match &compiled {
+            Some((_, Some(map))) => std::fs::write(lcov, coverage.lcov_mapped(map))?,
+            _ => std::fs::write(lcov, coverage.lcov(source))?,
+        }
+    }
+    if let Some(annotate) = args.value_of("annotate") {
+        match &compiled {
+            Some((src, _)) => std::fs::write(annotate, coverage.annotate(src))?,
+            None => {
+                eprintln!("Cannot annotate bytecode, collect coverage on the source code instead")
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Lint the program and print every warning
+fn lint(args: &ArgMatches) -> std::io::Result<()> {
+    let (program, _) = match load(args)? {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+
+    let source = args.value_of("source").unwrap();
+    let warnings = brainrust::lint(&program);
+    for warning in &warnings {
+        println!("{}:{}: warning: {}", source, warning.location, warning.kind);
+    }
+
+    if !warnings.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Format every source file in place, or only check that they are formatted
+fn fmt(args: &ArgMatches) -> std::io::Result<()> {
+    let comment = match comment(args) {
+        Ok(comment) => comment,
+        Err(err) => {
+            report_parse_error(err);
             return Ok(());
         }
-        Err(ParseError::EofBehavior(err)) => {
-            eprintln!("Failed to parse eof_behavior: \"{}\"", err);
+    };
+
+    let mut options = FormatOptions::default();
+    for (name, value) in [
+        ("width", &mut options.width),
+        ("indent", &mut options.indent),
+    ] {
+        if let Some(arg) = args.value_of(name) {
+            match arg.parse::<usize>() {
+                Ok(arg) => *value = arg,
+                Err(_) => {
+                    eprintln!("Failed to parse {}: \"{}\"", name, arg);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    let compile = |src: &str| match comment {
+        Some(comment) => Compiler::new().strict(comment).compile(String::from(src)),
+        None => Compiler::new().compile(String::from(src)),
+    };
+
+    let mut unformatted = false;
+    for source in args.values_of("sources").unwrap() {
+        let src = String::from_utf8_lossy(&std::fs::read(source)?).into_owned();
+        let program = match compile(&src) {
+            Ok(program) => program,
+            Err(errors) => {
+                eprintln!("{}:", source);
+                report_compile_errors(errors, None);
+                unformatted = true;
+                continue;
+            }
+        };
+
+        let formatted = format(&src, &program, &options);
+        match compile(&formatted) {
+            Ok(formatted) if formatted.commands() == program.commands() => {}
+            _ => {
+                eprintln!(
+                    "{}: formatting would change the program, left untouched",
+                    source
+                );
+                unformatted = true;
+                continue;
+            }
+        }
+
+        if formatted == src {
+            continue;
+        }
+        if args.is_present("check") {
+            println!("{}: not formatted", source);
+            unformatted = true;
+        } else {
+            std::fs::write(source, formatted)?;
+        }
+    }
+
+    if unformatted {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Minify the program, checking that the minified code optimizes the same
+fn minify(args: &ArgMatches) -> std::io::Result<()> {
+    let (program, settings) = match load(args)? {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+    let tape = match tape(args) {
+        Ok(tape) => tape.unwrap_or_default(),
+        Err(err) => {
+            report_parse_error(err);
             return Ok(());
         }
-        Err(ParseError::NewlineMode(err)) => {
-            eprintln!("Failed to parse newline_mode: \"{}\"", err);
+    };
+
+    let minified = brainrust::minify(&program, &settings, &tape);
+    let optimized = optimize(&program, &settings, &tape);
+    match Compiler::new().compile(minified.clone()) {
+        Ok(compiled) if optimize(&compiled, &settings, &tape) == optimized => {}
+        _ => {
+            eprintln!("Minified code does not behave the same, nothing written");
+            std::process::exit(1);
+        }
+    }
+
+    match args.value_of("output") {
+        Some(output) => std::fs::write(output, minified)?,
+        None => println!("{}", minified),
+    }
+    Ok(())
+}
+
+/// Observer noticing cells that overflow or underflow
+#[derive(Default)]
+struct Overflow {
+    overflowed: bool,
+}
+
+impl Observer for Overflow {
+    fn observe(&mut self, step: &Step) {
+        match step.command {
+            Command::Inc if step.before == 255 => self.overflowed = true,
+            Command::Dec if step.before == 0 => self.overflowed = true,
+            _ => {}
+        }
+    }
+}
+
+/// Generate a program printing the text, checking that it prints exactly the text
+fn gen_text(args: &ArgMatches) -> std::io::Result<()> {
+    let text = args.value_of("text").unwrap();
+    let text = if args.is_present("hex") {
+        match parse_hex(text) {
+            Some(text) => text,
+            None => {
+                eprintln!("Failed to parse text: \"{}\"", text);
+                return Ok(());
+            }
+        }
+    } else {
+        text.as_bytes().to_vec()
+    };
+
+    let mut options = TextOptions::default();
+    if let Some(cells) = args.value_of("cells") {
+        match cells.parse::<usize>() {
+            Ok(cells) if cells > 0 => options.cells = cells,
+            _ => {
+                eprintln!("Failed to parse cells: \"{}\"", cells);
+                return Ok(());
+            }
+        }
+    }
+    options.wrapping = !args.is_present("non_wrapping");
+
+    let code = generate_text(&text, &options);
+
+    let settings = Settings {
+        newline_mode: NewlineMode::LF,
+        ..Settings::default()
+    };
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let overflow = Rc::new(RefCell::new(Overflow::default()));
+    let mut interpreter = InterpreterBuilder::new(code.clone(), settings)
+        .input(Vec::new())
+        .output(output.clone())
+        .observer(Box::new(overflow.clone()))
+        .build()
+        .expect("generated code always compiles");
+    let mut state = interpreter.ready();
+    let result = interpreter.run(&mut state);
+
+    let printed = matches!(result, RunResult::Halted) && *output.borrow() == text;
+    if !printed || (!options.wrapping && overflow.borrow().overflowed) {
+        eprintln!("Generated code does not print the text, nothing written");
+        std::process::exit(1);
+    }
+
+    match args.value_of("output") {
+        Some(output) => std::fs::write(output, code)?,
+        None => println!("{}", code),
+    }
+    Ok(())
+}
+
+/// Whether the compiled `code` prints the same as running `src` directly on `input`. If either
+/// does not finish, only what both printed is compared.
+fn lang_runs_alike(src: &str, code: &str, input: &[u8]) -> bool {
+    let (expected, finished) = run_lang(src, input, LANG_STEPS).expect("the source compiled");
+    let settings = Settings {
+        newline_mode: NewlineMode::LF,
+        ..Settings::default()
+    };
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = InterpreterBuilder::new(code.to_string(), settings)
+        .input(input.to_vec())
+        .output(output.clone())
+        .build()
+        .expect("generated code always compiles");
+    let mut state = interpreter.ready();
+    let result = interpreter.run_for(&mut state, LANG_COMMANDS);
+
+    let output = output.borrow();
+    let len = output.len().min(expected.len());
+    match result {
+        RunResult::Halted if finished => *output == expected,
+        RunResult::Halted | RunResult::None => output[..len] == expected[..len],
+        _ => false,
+    }
+}
+
+/// Compile a program written in the high-level language into Brainfuck
+fn lang(args: &ArgMatches) -> std::io::Result<()> {
+    let source = args.value_of("source").unwrap();
+    let src = String::from_utf8_lossy(&std::fs::read(source)?).into_owned();
+    let code = match compile_lang(&src) {
+        Ok(code) => code,
+        Err(err) => {
+            report_lang_error(err);
             return Ok(());
         }
-        Err(ParseError::InputMode(err)) => {
-            eprintln!("Failed to parse input_mode: \"{}\"", err);
+    };
+
+    let same = [&[][..], LANG_INPUT]
+        .iter()
+        .all(|input| lang_runs_alike(&src, &code, input));
+    if !same {
+        eprintln!("Compiled code does not behave like the source, nothing written");
+        std::process::exit(1);
+    }
+
+    let output = match args.value_of("output") {
+        Some(output) => std::path::PathBuf::from(output),
+        None => std::path::Path::new(source).with_extension("bf"),
+    };
+    std::fs::write(output, code)
+}
+
+/// Print the program as pseudocode
+fn explain(args: &ArgMatches) -> std::io::Result<()> {
+    let (program, settings) = match load(args)? {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+
+    print!("{}", brainrust::explain(&program, &settings));
+    Ok(())
+}
+
+/// Print the control-flow graph of the program
+fn cfg(args: &ArgMatches) -> std::io::Result<()> {
+    let (program, _) = match load(args)? {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+
+    let cfg = Cfg::new(&program);
+    let graph = if args.is_present("dot") {
+        cfg.dot()
+    } else {
+        cfg.to_string()
+    };
+
+    match args.value_of("output") {
+        Some(output) => std::fs::write(output, graph),
+        None => {
+            print!("{}", graph);
+            Ok(())
+        }
+    }
+}
+
+/// Generate inputs reaching every loop edge and error, and list which one reaches what
+fn explore(args: &ArgMatches) -> std::io::Result<()> {
+    let (program, mut settings) = match load(args)? {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+    settings.ignore_newline = args.is_present("ignore_newline");
+
+    let mut max_steps = EXPLORE_STEPS;
+    if !number(args, "max_steps", &mut max_steps) {
+        return Ok(());
+    }
+
+    let cases = brainrust::explore(&program, &settings, max_steps);
+
+    // inputs reaching several targets are written once
+    let mut inputs: Vec<&[u8]> = Vec::new();
+    for case in &cases {
+        let index = match inputs.iter().position(|input| *input == &case.input[..]) {
+            Some(index) => index,
+            None => {
+                inputs.push(&case.input);
+                inputs.len() - 1
+            }
+        };
+        match args.value_of("output") {
+            Some(_) => println!("{}: {} -> {}.in", case.location, case.target, index + 1),
+            None => println!(
+                "{}: {} -> \"{}\"",
+                case.location,
+                case.target,
+                escape(&case.input)
+            ),
+        }
+    }
+
+    for (open, command) in program.commands().iter().enumerate() {
+        if let Command::Open(_) = command {
+            for edge in &[
+                EdgeKind::Enter,
+                EdgeKind::Skip,
+                EdgeKind::Repeat,
+                EdgeKind::Exit,
+            ] {
+                let target = Target::Loop(*edge);
+                if !cases
+                    .iter()
+                    .any(|case| case.target == target && case.command_ptr == open)
+                {
+                    println!("{}: {} not reached", program.locations()[open], target);
+                }
+            }
+        }
+    }
+
+    if let Some(dir) = args.value_of("output") {
+        std::fs::create_dir_all(dir)?;
+        for (i, input) in inputs.iter().enumerate() {
+            let path = std::path::Path::new(dir).join(format!("{}.in", i + 1));
+            std::fs::write(path, input)?;
+        }
+    }
+    Ok(())
+}
+
+/// Escape `bytes` so they can be printed between quotes
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|byte| std::ascii::escape_default(*byte))
+        .map(char::from)
+        .collect()
+}
+
+/// Check two programs behave the same on many inputs
+fn equiv(args: &ArgMatches) -> std::io::Result<()> {
+    let (a, mut settings) = match load(args)? {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+    let (b, _) = match load_source(args, args.value_of("other").unwrap())? {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+    settings.ignore_newline = args.is_present("ignore_newline");
+
+    let mut options = EquivOptions::default();
+    if !number(args, "max_length", &mut options.max_length)
+        || !number(args, "random", &mut options.random)
+        || !number(args, "random_length", &mut options.random_length)
+        || !number(args, "seed", &mut options.seed)
+        || !number(args, "max_steps", &mut options.max_steps)
+    {
+        return Ok(());
+    }
+    if let Some(alphabet) = args.value_of("alphabet") {
+        options.alphabet = alphabet.bytes().collect();
+        options.alphabet.sort_unstable();
+        options.alphabet.dedup();
+    }
+    options.compare_tape = !args.is_present("output_only");
+
+    let result = check_equivalence(&a, &b, &settings, &options);
+    if result.inconclusive > 0 {
+        eprintln!(
+            "Both programs ran out of steps on {} inputs, which were not compared",
+            result.inconclusive
+        );
+    }
+
+    match result.mismatch {
+        None => {
+            println!(
+                "Programs behave the same on {} input{}, covering every input up to length {}",
+                result.checked,
+                if result.checked == 1 { "" } else { "s" },
+                options.max_length
+            );
+            Ok(())
+        }
+        Some(mismatch) => {
+            println!(
+                "Programs behave differently on input \"{}\"",
+                escape(&mismatch.input)
+            );
+            let (a, b) = (mismatch.a, mismatch.b);
+            if a.output != b.output {
+                println!(
+                    "    output: \"{}\" vs \"{}\"",
+                    escape(&a.output),
+                    escape(&b.output)
+                );
+            }
+            if a.tape != b.tape && options.compare_tape {
+                println!("    tape: {:?} vs {:?}", a.tape, b.tape);
+            }
+            if a.ending != b.ending {
+                println!("    ending: {:?} vs {:?}", a.ending, b.ending);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Add the programs under `path` with a spec next to them to `programs`. A program given
+/// directly must have a spec.
+fn find_specs(
+    path: &std::path::Path,
+    direct: bool,
+    programs: &mut Vec<std::path::PathBuf>,
+) -> std::io::Result<()> {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            find_specs(&entry?.path(), false, programs)?;
+        }
+    } else if path.with_extension("toml").is_file()
+        && (direct || path.extension().is_some_and(|ext| ext == "bf"))
+    {
+        programs.push(path.to_path_buf());
+    } else if direct {
+        eprintln!("No spec found for {}", path.display());
+    }
+    Ok(())
+}
+
+/// Message showing where `actual` first differs from `expected`
+fn diff(expected: &[u8], actual: &[u8]) -> String {
+    let first = expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+    format!(
+        "expected \"{}\", got \"{}\" (first difference at byte {})",
+        escape(expected),
+        escape(actual),
+        first
+    )
+}
+
+/// Run the test cases of every program with a spec
+fn test(args: &ArgMatches) -> std::io::Result<()> {
+    let paths: Vec<&str> = args
+        .values_of("paths")
+        .map_or_else(|| vec!["."], |paths| paths.collect());
+    let mut jobs = std::thread::available_parallelism().map_or(1, |jobs| jobs.get());
+    if !number(args, "jobs", &mut jobs) {
+        return Ok(());
+    }
+
+    let mut paths_found = Vec::new();
+    for path in paths {
+        find_specs(std::path::Path::new(path), true, &mut paths_found)?;
+    }
+    paths_found.sort();
+
+    // programs and specs are loaded first, so errors are reported once and in order
+    let mut errors = 0;
+    let mut programs = Vec::new();
+    for path in paths_found {
+        let src = String::from_utf8_lossy(&std::fs::read(&path)?).into_owned();
+        let program = match Compiler::new().compile(src) {
+            Ok(program) => program,
+            Err(err) => {
+                eprintln!("ERROR {}: cannot compile", path.display());
+                report_compile_errors(err, None);
+                errors += 1;
+                continue;
+            }
+        };
+        let spec = match Spec::parse(&std::fs::read_to_string(path.with_extension("toml"))?) {
+            Ok(spec) => spec,
+            Err(err) => {
+                let message = match err {
+                    SpecError::Toml(message) => message,
+                    SpecError::InvalidSetting(name, val) => {
+                        format!("Invalid {}: \"{}\"", name, val)
+                    }
+                    SpecError::InvalidResult(result) => format!("Invalid result: \"{}\"", result),
+                };
+                let spec = path.with_extension("toml");
+                eprintln!("ERROR {}: {}", spec.display(), message.trim_end());
+                errors += 1;
+                continue;
+            }
+        };
+        programs.push((path, program, spec));
+    }
+
+    let cases: Vec<(usize, usize)> = programs
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (_, _, spec))| (0..spec.cases.len()).map(move |case| (i, case)))
+        .collect();
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let mut failures = vec![Vec::new(); cases.len()];
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let job = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        let (program, case) = match cases.get(job) {
+                            Some(case) => *case,
+                            None => return done,
+                        };
+                        let (_, program, spec) = &programs[program];
+                        done.push((job, spec.run(program, &spec.cases[case])));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            for (job, failed) in worker.join().unwrap() {
+                failures[job] = failed;
+            }
+        }
+    });
+
+    let (mut passed, mut failed) = (0, 0);
+    for (job, (program, case)) in cases.iter().enumerate() {
+        let (path, _, spec) = &programs[*program];
+        if failures[job].is_empty() {
+            passed += 1;
+            continue;
+        }
+
+        failed += 1;
+        println!("FAIL {}: {}", path.display(), spec.cases[*case].name);
+        print_failures(&failures[job]);
+    }
+
+    println!(
+        "{} passed, {} failed, {} programs with errors",
+        passed, failed, errors
+    );
+    if failed > 0 || errors > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Print how a test case failed.
+fn print_failures(failures: &[Failure]) {
+    for failure in failures {
+        match failure {
+            Failure::Output { expected, actual } => {
+                println!("    output: {}", diff(expected, actual))
+            }
+            Failure::Tape { expected, actual } => {
+                for (cell, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                    if expected != actual {
+                        println!(
+                            "    tape: cell {} expected {}, got {}",
+                            cell, expected, actual
+                        );
+                    }
+                }
+            }
+            Failure::Result { expected, actual } => {
+                println!("    result: expected {}, got {}", expected, actual)
+            }
+        }
+    }
+}
+
+/// Run the conformance suite and report the settings it fails under
+fn selftest() -> std::io::Result<()> {
+    let (nonconformances, runs) = brainrust::selftest();
+    for nonconformance in &nonconformances {
+        println!(
+            "FAIL {} with {}",
+            nonconformance.name,
+            settings_flags(&nonconformance.settings)
+        );
+        print_failures(&nonconformance.failures);
+    }
+
+    println!(
+        "{} passed, {} failed",
+        runs - nonconformances.len(),
+        nonconformances.len()
+    );
+    if !nonconformances.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Command-line options giving `settings`
+fn settings_flags(settings: &Settings) -> String {
+    let mut flags = Vec::new();
+    if settings.dynamic_size {
+        flags.push("--dynamic_size".to_string());
+    }
+    flags.push(format!("--array_size {}", settings.array_size));
+    if settings.wrapping {
+        flags.push("--wrapping".to_string());
+    }
+    let eof_behavior = match settings.eof_behavior {
+        EofBehavior::AsIs => "as_is",
+        EofBehavior::NegativeOne => "negative_one",
+        EofBehavior::Zero => "zero",
+    };
+    flags.push(format!("--eof_behavior {}", eof_behavior));
+    let newline_mode = match settings.newline_mode {
+        NewlineMode::CRLF => "CRLF",
+        NewlineMode::LF => "LF",
+    };
+    flags.push(format!("--newline_mode {}", newline_mode));
+    if settings.ignore_newline {
+        flags.push("--ignore_newline".to_string());
+    }
+    let input_mode = match settings.input_mode {
+        InputMode::Ascii => "ascii",
+        InputMode::Digit => "digit",
+    };
+    flags.push(format!("--input_mode {}", input_mode));
+    flags.join(" ")
+}
+
+/// Run the program
+fn run(args: &ArgMatches) -> std::io::Result<()> {
+    let (program, settings) = match load(args)? {
+        Some(loaded) => loaded,
+        None => return Ok(()),
+    };
+
+    let input = match input(args) {
+        Ok(input) => input,
+        Err(err) => {
+            report_parse_error(err);
             return Ok(());
         }
     };
 
-    let src = read_file(args.value_of("source").unwrap())?;
+    let tape = match tape(args) {
+        Ok(tape) => tape,
+        Err(err) => {
+            report_parse_error(err);
+            return Ok(());
+        }
+    };
+
+    let residual = if args.is_present("partial") {
+        let tape = tape.as_deref().unwrap_or(&[]);
+        Some(partial_evaluate(&program, &settings, tape, PARTIAL_STEPS))
+    } else {
+        None
+    };
+    let program = match &residual {
+        Some(residual) => residual.program.clone(),
+        None => program,
+    };
 
-    let mut interpreter = match InterpreterBuilder::new(src, settings)
+    let mut builder = InterpreterBuilder::from_program(program.clone(), settings)
         .reader(args.value_of("input"))?
-        .writer(args.value_of("output"))?
-        .build()
+        .writer(args.value_of("output"))?;
+    if let Some(input) = input {
+        builder = builder.input(input);
+    }
+    if let Some(tape) = tape {
+        builder = builder.tape(tape);
+    }
+    if args.is_present("detect_loops") {
+        builder = builder.detect_loops();
+    }
+    if let Some(trace) = args.value_of("trace") {
+        let format = match trace_format(args) {
+            Ok(format) => format,
+            Err(err) => {
+                report_parse_error(err);
+                return Ok(());
+            }
+        };
+        let writer = Box::new(std::io::BufWriter::new(std::fs::File::create(trace)?));
+        builder = builder.observer(Box::new(Tracer::new(writer, format)));
+    }
+
+    let profiler = if args.is_present("profile")
+        || args.is_present("profile_listing")
+        || args.is_present("profile_folded")
     {
+        let profiler = Rc::new(RefCell::new(Profiler::new(&program)));
+        builder = builder.observer(Box::new(profiler.clone()));
+        Some(profiler)
+    } else {
+        None
+    };
+
+    let mut interpreter = match builder.build() {
         Ok(interpreter) => interpreter,
-        Err(CompileError::Syntax(loc, ch)) => {
-            eprintln!("Invalid syntax at {}: '{}'", loc, ch);
-            return Ok(());
-        }
-        Err(CompileError::UnmatchedBracket(loc)) => {
-            eprintln!("Cannot find matching bracket at {}", loc);
+        Err(errors) => {
+            report_compile_errors(errors, None);
             return Ok(());
         }
     };
 
-    let mut state = interpreter.ready();
+    let mut state = match &residual {
+        Some(residual) => {
+            if interpreter.writer.write_all(&residual.output).is_err()
+                || interpreter.writer.flush().is_err()
+            {
+                eprintln!("Failed to write");
+                return Ok(());
+            }
+            residual.ready()
+        }
+        None => interpreter.ready(),
+    };
     loop {
         let result = interpreter.next(&mut state);
         match result {
@@ -194,9 +1709,31 @@ fn main() -> std::io::Result<()> {
                 eprintln!("Failed to parse input into number");
                 break;
             }
+            RunResult::InfiniteLoop { location } => {
+                eprintln!("Infinite loop at {}", location);
+                break;
+            }
         };
     }
 
+    interpreter.finish()?;
+
+    if let Some(profiler) = profiler {
+        let profiler = profiler.borrow();
+        if args.is_present("profile") {
+            eprint!("\n{}", profiler.report(10));
+        }
+        if let Some(folded) = args.value_of("profile_folded") {
+            std::fs::write(folded, profiler.folded())?;
+        }
+        if let Some(listing) = args.value_of("profile_listing") {
+            match compiled_source(args)? {
+                Some((src, _)) => std::fs::write(listing, profiler.annotate(&src))?,
+                None => eprintln!("Cannot annotate bytecode, profile the source code instead"),
+            }
+        }
+    }
+
     if args.is_present("final_array") {
         println!("\nFinal array: {:?}", state.cells.0)
     }
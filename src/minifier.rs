@@ -0,0 +1,47 @@
+//! Minifier, writing the shortest source code with the same optimized form as a program, for the
+//! settings and the initial tape it is run with.
+//!
+//! Comments are stripped, commands cancelling each other are removed, and loops that can never
+//! run are dropped. Additions are written in whichever direction is shorter, e.g. 255 `+` become
+//! one `-`.
+//!
+//! Minified code fails out of bounds at the same command as the program: loops are written in
+//! their original order, and `+-` is kept on cells that may lie outside the array.
+
+use crate::optimizer::*;
+use crate::program::*;
+use crate::settings::*;
+
+/// Minify `program`, run with `settings` from the initial values `tape`.
+pub fn minify(program: &Program, settings: &Settings, tape: &[u8]) -> String {
+    let mut minified = String::new();
+    write_ops(&mut minified, &optimize_in_order(program, settings, tape));
+    minified
+}
+
+fn write_ops(minified: &mut String, ops: &[Op]) {
+    for op in ops {
+        match op {
+            // kept only where it may fail out of bounds
+            Op::Add(0) => minified.push_str("+-"),
+            Op::Add(val) if *val <= 128 => repeat(minified, '+', *val as usize),
+            Op::Add(val) => repeat(minified, '-', 256 - *val as usize),
+            Op::Move(offset) if *offset > 0 => repeat(minified, '>', *offset as usize),
+            Op::Move(offset) => repeat(minified, '<', offset.unsigned_abs()),
+            Op::Read => minified.push(','),
+            Op::Write => minified.push('.'),
+            Op::Clear | Op::Multiply(_) => {
+                unreachable!("optimize_in_order leaves loops as written")
+            }
+            Op::Loop(body) => {
+                minified.push('[');
+                write_ops(minified, body);
+                minified.push(']');
+            }
+        }
+    }
+}
+
+fn repeat(minified: &mut String, ch: char, count: usize) {
+    minified.extend(std::iter::repeat_n(ch, count));
+}
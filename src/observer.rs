@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::location::*;
+use crate::program::*;
+
+/// A command that has just been executed
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// Number of commands executed before this one
+    pub step: u64,
+    pub command_ptr: usize,
+    pub command: Command,
+    /// Location of the command in the source code
+    pub location: Location,
+    /// Cell pointer before the command was executed
+    pub cell_ptr: usize,
+    /// Value of the cell at `cell_ptr` before the command was executed
+    pub before: u8,
+    /// Value of the cell at `cell_ptr` after the command was executed
+    pub after: u8,
+}
+
+/// Observer of the execution. Observers are notified by `Interpreter::next` after every command
+/// that executes successfully.
+pub trait Observer {
+    fn observe(&mut self, step: &Step);
+
+    /// Called once the program stopped. Observers that write their results somewhere should do so
+    /// here.
+    fn finish(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Shared observer, so its results can still be read after handing it to the interpreter.
+impl<T: Observer> Observer for Rc<RefCell<T>> {
+    fn observe(&mut self, step: &Step) {
+        self.borrow_mut().observe(step);
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.borrow_mut().finish()
+    }
+}
@@ -0,0 +1,211 @@
+//! Optimized form of a program. Runs of commands are merged, commands cancelling each other are
+//! removed, and loops that can never run are dropped. Loops clearing a cell, or adding multiples
+//! of it to other cells, are recognized.
+//!
+//! Two programs with the same optimized form behave the same, when run with the settings and the
+//! initial tape the form was made for. This includes accesses to cells outside the array: commands
+//! cancelling each other are only removed on a cell already accessed. The exception is clears and
+//! multiplications: when they access a cell outside the array, the commands of the loop may have
+//! run in a different order before it fails. `optimize_in_order` leaves such loops as they are.
+
+use std::collections::BTreeMap;
+
+use crate::ast::*;
+use crate::program::*;
+use crate::settings::*;
+
+/// Instruction of the optimized form
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// Add to the current cell, wrapping around
+    Add(u8),
+    /// Move the cell pointer by the given number of cells
+    Move(isize),
+    Read,
+    Write,
+    /// Set the current cell to 0, e.g. `[-]`
+    Clear,
+    /// For each offset and factor, add the current cell times the factor to the cell at that
+    /// offset, wrapping around. Then set the current cell to 0. E.g. `[->+>++<<]` is
+    /// `Multiply(vec![(1, 1), (2, 2)])`. Offsets are in increasing order.
+    Multiply(Vec<(isize, u8)>),
+    /// Run the body while the current cell is not 0
+    Loop(Vec<Op>),
+}
+
+/// Optimize `program`, run with `settings` from the initial values `tape`.
+pub fn optimize(program: &Program, settings: &Settings, tape: &[u8]) -> Vec<Op> {
+    Optimizer::new(settings, tape, true).block(&Block::from(program))
+}
+
+/// Optimize `program` like `optimize`, without recognizing clears and multiplications, so loops
+/// run their commands in the order they are written.
+pub fn optimize_in_order(program: &Program, settings: &Settings, tape: &[u8]) -> Vec<Op> {
+    Optimizer::new(settings, tape, false).block(&Block::from(program))
+}
+
+struct Optimizer {
+    /// Whether the current cell is known to be 0
+    known_zero: bool,
+    /// Whether the current cell is known to be inside the array, because it was accessed since
+    /// the pointer last moved
+    checked: bool,
+    /// Whether the current cell was known to be inside the array before the run of additions
+    /// the ops end with
+    checked_before_adds: bool,
+    /// Whether moves in opposite directions cancel each other
+    cancel_moves: bool,
+    /// Whether loops are recognized as clears and multiplications
+    recognize: bool,
+}
+
+impl Optimizer {
+    fn new(settings: &Settings, tape: &[u8], recognize: bool) -> Self {
+        // the first cell exists unless the array is fixed and empty. An empty dynamic array grows
+        // when the cell is accessed, which changes where '<' wraps around to, so the access counts.
+        let grows = settings.wrapping && settings.array_size == 0 && tape.is_empty();
+        let first_cell = (settings.dynamic_size && !grows) || settings.array_size > 0;
+        Self {
+            known_zero: first_cell && tape.iter().all(|cell| *cell == 0),
+            checked: first_cell,
+            checked_before_adds: first_cell,
+            // '<' on the first cell of a dynamic array wraps around to the last cell, but '>' on
+            // the last cell grows the array, so they don't cancel each other
+            cancel_moves: !(settings.dynamic_size && settings.wrapping),
+            recognize,
+        }
+    }
+
+    fn block(&mut self, block: &Block) -> Vec<Op> {
+        let mut ops = Vec::new();
+        for node in &block.0 {
+            let op = match node {
+                Node::Right(_) => Op::Move(1),
+                Node::Left(_) => Op::Move(-1),
+                Node::Inc(_) => Op::Add(1),
+                Node::Dec(_) => Op::Add(u8::MAX),
+                Node::Read(_) => Op::Read,
+                Node::Write(_) => Op::Write,
+                Node::Loop(body, _, _) => {
+                    // the loop can never run
+                    if self.known_zero {
+                        continue;
+                    }
+
+                    // '[' accesses the cell
+                    self.checked = true;
+                    let body = self.block(body);
+                    self.known_zero = true;
+                    self.checked = true;
+                    let moves = body.iter().any(|op| matches!(op, Op::Move(_)));
+                    let op = if self.recognize && (self.cancel_moves || !moves) {
+                        recognize(body)
+                    } else {
+                        Op::Loop(body)
+                    };
+                    self.push(&mut ops, op);
+                    continue;
+                }
+            };
+
+            match op {
+                Op::Move(_) => {
+                    self.known_zero = false;
+                    self.checked = false;
+                }
+                Op::Read => self.known_zero = false,
+                Op::Write => self.checked = true,
+                _ => {
+                    if !matches!(ops.last(), Some(Op::Add(_))) {
+                        self.checked_before_adds = self.checked;
+                    }
+                    self.known_zero = false;
+                    self.checked = true;
+                }
+            }
+            self.push(&mut ops, op);
+        }
+
+        // whatever follows the block, nothing is known
+        self.known_zero = false;
+        ops
+    }
+
+    /// Push `op`, merging it with the last op if they are of the same kind
+    fn push(&self, ops: &mut Vec<Op>, op: Op) {
+        let merged = match (ops.last(), &op) {
+            (Some(Op::Add(last)), Op::Add(val)) => Op::Add(last.wrapping_add(*val)),
+            (Some(Op::Move(last)), Op::Move(val))
+                if self.cancel_moves || last.signum() == val.signum() =>
+            {
+                Op::Move(last + val)
+            }
+            _ => {
+                ops.push(op);
+                return;
+            }
+        };
+
+        // additions cancelling each other still fail on a cell outside the array
+        ops.pop();
+        let dropped = match merged {
+            Op::Add(0) => self.checked_before_adds,
+            Op::Move(0) => true,
+            _ => false,
+        };
+        if !dropped {
+            ops.push(merged);
+        }
+    }
+}
+
+/// Recognize what a loop does from its body
+fn recognize(body: Vec<Op>) -> Op {
+    // an odd step reaches 0 from every value
+    if let [Op::Add(step)] = body[..] {
+        if step % 2 == 1 {
+            return Op::Clear;
+        }
+    }
+
+    let mut offset = 0;
+    let mut adds = BTreeMap::new();
+    for op in &body {
+        match op {
+            Op::Move(val) => offset += val,
+            Op::Add(val) => {
+                let add = adds.entry(offset).or_insert(0u8);
+                *add = add.wrapping_add(*val);
+            }
+            _ => return Op::Loop(body),
+        }
+    }
+    if offset != 0 {
+        return Op::Loop(body);
+    }
+
+    // the loop runs as many times as the cell value when counting down, or 256 minus that when
+    // counting up
+    let negate = match adds.remove(&0) {
+        Some(255) => false,
+        Some(1) => true,
+        _ => return Op::Loop(body),
+    };
+    let targets: Vec<(isize, u8)> = adds
+        .into_iter()
+        .filter(|(_, factor)| *factor != 0)
+        .map(|(offset, factor)| {
+            if negate {
+                (offset, factor.wrapping_neg())
+            } else {
+                (offset, factor)
+            }
+        })
+        .collect();
+
+    if targets.is_empty() {
+        Op::Clear
+    } else {
+        Op::Multiply(targets)
+    }
+}
@@ -0,0 +1,104 @@
+//! Partial evaluation. The part of a program that runs before its first ',' doesn't depend on the
+//! input, so it can be run once ahead of time. What remains is a residual program, the state it
+//! starts from, and the output already written.
+//!
+//! Running the residual program from its state, after writing the recorded output, behaves the
+//! same as running the whole program. Programs that never read, such as
+//! `examples/hello_world.txt`, are left with nothing but their output.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::error::*;
+use crate::interpreter::*;
+use crate::interpreter_builder::*;
+use crate::program::*;
+use crate::settings::*;
+
+/// Default number of commands run ahead of time before giving up, for programs that loop forever
+/// or run too long without reading.
+pub const PARTIAL_STEPS: u64 = 10_000_000;
+
+/// Program left after partial evaluation
+pub struct Residual {
+    pub program: Program,
+    /// State to start running `program` from
+    pub state: State,
+    /// Output written ahead of time, to write before running `program`
+    pub output: Vec<u8>,
+}
+
+impl Residual {
+    /// Return a new state for running the residual program.
+    pub fn ready(&self) -> State {
+        self.state.clone()
+    }
+}
+
+/// Run `program` from the initial values `tape` until it reads input, stops, or has run
+/// `max_steps` commands. A command that fails, such as a move out of bounds, is left for the
+/// residual program to run.
+pub fn partial_evaluate(
+    program: &Program,
+    settings: &Settings,
+    tape: &[u8],
+    max_steps: u64,
+) -> Residual {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = InterpreterBuilder::from_program(program.clone(), settings.clone())
+        .tape(tape.to_vec())
+        .input(Vec::new())
+        .output(output.clone())
+        .build()
+        .unwrap();
+
+    let mut state = interpreter.ready();
+    while state.steps < max_steps {
+        let command = interpreter.program.commands[state.command_ptr];
+        if matches!(command, Command::Read | Command::Halt) {
+            break;
+        }
+
+        // a failed command leaves the state as it was, to fail again when run for real
+        let next_step = state.steps + 1;
+        if !matches!(interpreter.run_for(&mut state, next_step), RunResult::None) {
+            break;
+        }
+    }
+
+    let program = suffix(program, &mut state);
+    let output = output.borrow().clone();
+    Residual {
+        program,
+        state,
+        output,
+    }
+}
+
+/// Drop the commands already run, if `state` is outside every loop, so none of them can run again.
+fn suffix(program: &Program, state: &mut State) -> Program {
+    let start = state.command_ptr;
+    let inside_loop = program.commands[..start]
+        .iter()
+        .any(|command| match command {
+            Command::Open(close) => *close >= start,
+            _ => false,
+        });
+    if inside_loop {
+        return program.clone();
+    }
+
+    let commands = program.commands[start..]
+        .iter()
+        .map(|command| match command {
+            Command::Open(close) => Command::Open(close - start),
+            Command::Close(open) => Command::Close(open - start),
+            command => *command,
+        })
+        .collect();
+    state.command_ptr = 0;
+    Program {
+        commands,
+        locations: program.locations[start..].to_vec(),
+    }
+}
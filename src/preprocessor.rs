@@ -0,0 +1,574 @@
+//! Macro preprocessor, run on source code before compiling it.
+//!
+//! ```text
+//! #include "lib/copy.bf"          // path relative to the including file
+//! #define CLEAR [-]
+//! // `{code}*count` repeats code
+//! #define RIGHT(n) {>}*n
+//! #define ADD(n, to) RIGHT(to) {+}*n {<}*to
+//!
+//! CLEAR ADD(10, 2)
+//! ```
+//!
+//! - Directives take a whole line. A `#define` body runs to the end of the line, and continues on
+//!   the next line if the line ends with `\`.
+//! - A macro is expanded wherever its name appears as a whole word, including inside comments, so
+//!   names are best kept distinct from ordinary words, e.g. in uppercase. A macro with parameters
+//!   is only expanded when its name is directly followed by `(`.
+//! - Macros are global: they can be used in every file and before their definition.
+//! - A file is only included once, later includes of the same file are ignored.
+//! - A repeat count is a number, or a macro expanding to a number. `{` only starts a repeat when
+//!   its `}` is followed by `*` and a count, so braces in comments are kept as they are. A repeat
+//!   expands to at most `MAX_REPEAT` characters.
+//! - Macros and repeats expand to at most `MAX_EXPANSION` characters in total, counting the
+//!   expansions nested in others too.
+//!
+//! Every character of the output remembers where it came from, so compile errors can be reported
+//! in the original files. Code coming from a macro points into the macro definition.
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::error::*;
+use crate::location::*;
+
+/// Largest number of characters a repeat expands to
+pub const MAX_REPEAT: usize = 1 << 20;
+
+/// Largest number of characters all macros and repeats expand to, nested expansions included
+pub const MAX_EXPANSION: usize = 4 * MAX_REPEAT;
+
+/// Where a character of the preprocessed source comes from
+#[derive(Clone, Copy, Debug)]
+struct Origin {
+    /// Index of the file in `SourceMap::files`
+    file: usize,
+    location: Location,
+}
+
+/// Characters with their origin
+type Text = Vec<(char, Origin)>;
+
+/// Mapping from the preprocessed source back to the original files
+#[derive(Clone, Debug)]
+pub struct SourceMap {
+    /// Every file read, in order
+    pub files: Vec<PathBuf>,
+    /// Origin of every character of the preprocessed source
+    origins: Vec<Origin>,
+}
+
+impl SourceMap {
+    /// File and location that the character at `location` of the preprocessed source comes
+    /// from. Locations past the end map to the last character.
+    pub fn lookup(&self, location: Location) -> Option<(&Path, Location)> {
+        let origin = self
+            .origins
+            .get(location.index)
+            .or_else(|| self.origins.last())?;
+        Some((&self.files[origin.file], origin.location))
+    }
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Text,
+}
+
+/// Preprocessor
+pub struct Preprocessor {
+    files: Vec<PathBuf>,
+    /// Canonical paths of the files read, so each is only included once
+    included: HashSet<PathBuf>,
+    macros: HashMap<String, Macro>,
+    /// Number of characters macros and repeats expanded to so far
+    expanded: Cell<usize>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            included: HashSet::new(),
+            macros: HashMap::new(),
+            expanded: Cell::new(0),
+        }
+    }
+
+    /// Preprocess the file at `path`.
+    pub fn preprocess_file(self, path: &Path) -> Result<(String, SourceMap), PreprocessError> {
+        let src = read(path)?;
+        self.preprocess(&src, path)
+    }
+
+    /// Preprocess `src`, read from `path`. Includes are relative to `path`.
+    pub fn preprocess(
+        mut self,
+        src: &str,
+        path: &Path,
+    ) -> Result<(String, SourceMap), PreprocessError> {
+        if let Ok(path) = path.canonicalize() {
+            self.included.insert(path);
+        }
+        let text = self.file(src, path)?;
+        let text = self.expand(&text, &mut Vec::new())?;
+
+        let output = text.iter().map(|(ch, _)| *ch).collect();
+        let map = SourceMap {
+            files: self.files,
+            origins: text.into_iter().map(|(_, origin)| origin).collect(),
+        };
+        Ok((output, map))
+    }
+
+    /// Read the directives of a file, returning the rest of its text with included files in
+    /// place
+    fn file(&mut self, src: &str, path: &Path) -> Result<Text, PreprocessError> {
+        let file = self.files.len();
+        self.files.push(path.to_path_buf());
+
+        let mut chars = Text::new();
+        let mut loc = Location {
+            index: 0,
+            line: 1,
+            column: 1,
+        };
+        for (i, ch) in src.chars().enumerate() {
+            loc.index = i;
+            chars.push((
+                ch,
+                Origin {
+                    file,
+                    location: loc,
+                },
+            ));
+            if ch == '\n' {
+                loc.line += 1;
+                loc.column = 1;
+            } else {
+                loc.column += 1;
+            }
+        }
+
+        let mut lines = chars
+            .split_inclusive(|(ch, _)| *ch == '\n')
+            .map(|line| line.to_vec());
+        let mut text = Text::new();
+        while let Some(line) = lines.next() {
+            let start = line
+                .iter()
+                .position(|(ch, _)| !ch.is_whitespace())
+                .unwrap_or(line.len());
+            let directive: String = line[start..].iter().map(|(ch, _)| *ch).collect();
+            let newline = line.last().copied().filter(|(ch, _)| *ch == '\n');
+
+            if is_directive(&directive, "#define") {
+                let mut line = line;
+                // continuation lines
+                while ends_with_backslash(&line) {
+                    while line.last().is_some_and(|(ch, _)| *ch != '\\') {
+                        line.pop();
+                    }
+                    line.pop();
+                    match lines.next() {
+                        Some(next) => line.extend(next),
+                        None => break,
+                    }
+                }
+                self.define(&line[start + "#define".len()..], line[start].1)?;
+            } else if is_directive(&directive, "#include") {
+                let included = self.include(&directive["#include".len()..], line[start].1)?;
+                text.extend(included);
+            } else {
+                text.extend(line);
+                continue;
+            }
+
+            // keep the line break of the directive
+            text.extend(newline);
+        }
+
+        Ok(text)
+    }
+
+    /// Read `#define` after the keyword
+    fn define(&mut self, rest: &[(char, Origin)], origin: Origin) -> Result<(), PreprocessError> {
+        let invalid = || PreprocessError::InvalidDefine(self.path(origin), origin.location);
+
+        let mut i = skip_whitespace(rest, 0);
+        let name = match ident(rest, i) {
+            Some(name) => name,
+            None => return Err(invalid()),
+        };
+        i += name.len();
+
+        let mut params = Vec::new();
+        if rest.get(i).is_some_and(|(ch, _)| *ch == '(') {
+            let close = match rest[i..].iter().position(|(ch, _)| *ch == ')') {
+                Some(close) => i + close,
+                None => return Err(invalid()),
+            };
+            let list: String = rest[i + 1..close].iter().map(|(ch, _)| *ch).collect();
+            for param in list.split(',') {
+                let param = param.trim();
+                if ident(&chars(param), 0).is_none_or(|ident| ident.len() != param.len()) {
+                    return Err(invalid());
+                }
+                params.push(String::from(param));
+            }
+            i = close + 1;
+        }
+
+        let mut body = rest[i..].to_vec();
+        while body.first().is_some_and(|(ch, _)| ch.is_whitespace()) {
+            body.remove(0);
+        }
+        while body.last().is_some_and(|(ch, _)| ch.is_whitespace()) {
+            body.pop();
+        }
+
+        if self.macros.contains_key(&name) {
+            return Err(PreprocessError::Redefined(
+                self.path(origin),
+                origin.location,
+                name,
+            ));
+        }
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    /// Read `#include` after the keyword, returning the text of the included file
+    fn include(&mut self, rest: &str, origin: Origin) -> Result<Text, PreprocessError> {
+        // anything after the quoted name is a comment
+        let name = match rest.trim_start().strip_prefix('"') {
+            Some(quoted) => quoted.split('"').next().filter(|_| quoted.contains('"')),
+            None => None,
+        };
+        let name = match name {
+            Some(name) if !name.is_empty() => name,
+            _ => {
+                return Err(PreprocessError::InvalidInclude(
+                    self.path(origin),
+                    origin.location,
+                ))
+            }
+        };
+
+        let including = self.path(origin);
+        let path = match including.parent() {
+            Some(dir) => dir.join(name),
+            None => PathBuf::from(name),
+        };
+        let canonical = path
+            .canonicalize()
+            .map_err(|err| PreprocessError::Io(path.clone(), err))?;
+        if !self.included.insert(canonical) {
+            return Ok(Text::new());
+        }
+
+        let src = read(&path)?;
+        self.file(&src, &path)
+    }
+
+    /// Expand macros and repeats in `text`. `active` are the macros being expanded, to catch
+    /// macros expanding to themselves.
+    fn expand(
+        &self,
+        text: &[(char, Origin)],
+        active: &mut Vec<String>,
+    ) -> Result<Text, PreprocessError> {
+        let mut expanded = Text::new();
+        let mut i = 0;
+        while i < text.len() {
+            let (ch, origin) = text[i];
+
+            if ch == '{' {
+                if let Some((close, count, end)) = self.repeat(text, i, active)? {
+                    let body = self.expand(&text[i + 1..close], active)?;
+                    if body.len().saturating_mul(count) > MAX_REPEAT {
+                        return Err(PreprocessError::RepeatTooLarge(
+                            self.path(origin),
+                            origin.location,
+                        ));
+                    }
+                    // an empty body may have any count
+                    if !body.is_empty() {
+                        self.count(body.len() * count, origin)?;
+                        for _ in 0..count {
+                            expanded.extend_from_slice(&body);
+                        }
+                    }
+                    i = end;
+                    continue;
+                }
+            }
+
+            let name = match ident(text, i) {
+                Some(name) => name,
+                None => {
+                    expanded.push((ch, origin));
+                    i += 1;
+                    continue;
+                }
+            };
+            let end = i + name.chars().count();
+            let found = match self.macros.get(&name) {
+                Some(found) => found,
+                None => {
+                    expanded.extend_from_slice(&text[i..end]);
+                    i = end;
+                    continue;
+                }
+            };
+
+            let (args, end) = if found.params.is_empty() {
+                (Vec::new(), end)
+            } else if text.get(end).is_some_and(|(ch, _)| *ch == '(') {
+                self.args(text, end, &name, origin)?
+            } else {
+                // not an invocation
+                expanded.extend_from_slice(&text[i..end]);
+                i = end;
+                continue;
+            };
+
+            if args.len() != found.params.len() {
+                return Err(PreprocessError::ArgumentCount(
+                    self.path(origin),
+                    origin.location,
+                    name,
+                    found.params.len(),
+                ));
+            }
+            if active.contains(&name) {
+                return Err(PreprocessError::RecursiveMacro(
+                    self.path(origin),
+                    origin.location,
+                    name,
+                ));
+            }
+
+            let body = substitute(&found.body, &found.params, &args);
+            active.push(name);
+            let body = self.expand(&body, active)?;
+            active.pop();
+            self.count(body.len(), origin)?;
+            expanded.extend(body);
+            i = end;
+        }
+
+        Ok(expanded)
+    }
+
+    /// Read the arguments of a macro invocation, from the `(` at `open`. Return the arguments and
+    /// the index after the `)`.
+    fn args(
+        &self,
+        text: &[(char, Origin)],
+        open: usize,
+        name: &str,
+        origin: Origin,
+    ) -> Result<(Vec<Text>, usize), PreprocessError> {
+        let mut args = vec![Text::new()];
+        let mut depth = 0;
+        for (i, (ch, ch_origin)) in text.iter().enumerate().skip(open + 1) {
+            match ch {
+                ')' if depth == 0 => {
+                    let args = args.into_iter().map(|arg| trim(&arg)).collect();
+                    return Ok((args, i + 1));
+                }
+                ',' if depth == 0 => {
+                    args.push(Text::new());
+                    continue;
+                }
+                '(' | '{' => depth += 1,
+                ')' | '}' => depth -= 1,
+                _ => {}
+            }
+            args.last_mut().unwrap().push((*ch, *ch_origin));
+        }
+
+        Err(PreprocessError::ArgumentCount(
+            self.path(origin),
+            origin.location,
+            String::from(name),
+            self.macros[name].params.len(),
+        ))
+    }
+
+    /// Read a repeat from the `{` at `open`. Return the index of its `}`, its count, and the index
+    /// after it, or `None` if the `{` is not followed by a matching `}`, `*` and a number or a
+    /// macro name.
+    fn repeat(
+        &self,
+        text: &[(char, Origin)],
+        open: usize,
+        active: &mut Vec<String>,
+    ) -> Result<Option<(usize, usize, usize)>, PreprocessError> {
+        let origin = text[open].1;
+
+        let mut depth = 0;
+        let mut close = None;
+        for (i, (ch, _)) in text.iter().enumerate().skip(open) {
+            match ch {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(i);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let close = match close {
+            Some(close) if text.get(close + 1).is_some_and(|(ch, _)| *ch == '*') => close,
+            _ => return Ok(None),
+        };
+
+        let start = close + 2;
+        let mut end = start;
+        while text.get(end).is_some_and(|(ch, _)| ch.is_ascii_digit()) {
+            end += 1;
+        }
+        let count: String = if end > start {
+            text[start..end].iter().map(|(ch, _)| *ch).collect()
+        } else {
+            // count given by a macro
+            let name = match ident(text, start) {
+                Some(name) if self.macros.contains_key(&name) => name,
+                _ => return Ok(None),
+            };
+            end = start + name.chars().count();
+            let expanded = self.expand(&text[start..end], active)?;
+            expanded.iter().map(|(ch, _)| *ch).collect()
+        };
+
+        let count = count.trim();
+        if !count.is_empty() && count.chars().all(|ch| ch.is_ascii_digit()) {
+            // too many digits for usize is too large anyway
+            let count = count.parse::<usize>().unwrap_or(usize::MAX);
+            Ok(Some((close, count, end)))
+        } else {
+            Err(PreprocessError::InvalidRepeat(
+                self.path(origin),
+                origin.location,
+            ))
+        }
+    }
+
+    /// Count `len` more characters expanded by the macro or repeat at `origin`
+    fn count(&self, len: usize, origin: Origin) -> Result<(), PreprocessError> {
+        let expanded = self.expanded.get() + len;
+        if expanded > MAX_EXPANSION {
+            return Err(PreprocessError::ExpansionTooLarge(
+                self.path(origin),
+                origin.location,
+            ));
+        }
+        self.expanded.set(expanded);
+        Ok(())
+    }
+
+    fn path(&self, origin: Origin) -> PathBuf {
+        self.files[origin.file].clone()
+    }
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read(path: &Path) -> Result<String, PreprocessError> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        Err(err) => Err(PreprocessError::Io(path.to_path_buf(), err)),
+    }
+}
+
+/// Whether `line` starts with the directive `name`, followed by whitespace or nothing
+fn is_directive(line: &str, name: &str) -> bool {
+    line.strip_prefix(name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+fn ends_with_backslash(line: &[(char, Origin)]) -> bool {
+    line.iter()
+        .rev()
+        .find(|(ch, _)| !ch.is_whitespace())
+        .is_some_and(|(ch, _)| *ch == '\\')
+}
+
+/// Identifier starting at `start`, if any. An identifier must not continue a longer word.
+fn ident(text: &[(char, Origin)], start: usize) -> Option<String> {
+    let is_start = |ch: char| ch.is_ascii_alphabetic() || ch == '_';
+    let is_part = |ch: char| ch.is_ascii_alphanumeric() || ch == '_';
+
+    if !text.get(start).is_some_and(|(ch, _)| is_start(*ch)) {
+        return None;
+    }
+    if start > 0 && is_part(text[start - 1].0) {
+        return None;
+    }
+
+    Some(
+        text[start..]
+            .iter()
+            .map(|(ch, _)| *ch)
+            .take_while(|ch| is_part(*ch))
+            .collect(),
+    )
+}
+
+fn skip_whitespace(text: &[(char, Origin)], mut i: usize) -> usize {
+    while text.get(i).is_some_and(|(ch, _)| ch.is_whitespace()) {
+        i += 1;
+    }
+    i
+}
+
+fn trim(text: &[(char, Origin)]) -> Text {
+    let start = skip_whitespace(text, 0);
+    let end = text
+        .iter()
+        .rposition(|(ch, _)| !ch.is_whitespace())
+        .map_or(start, |end| end + 1);
+    text[start..end.max(start)].to_vec()
+}
+
+/// Characters of `text`, without an origin
+fn chars(text: &str) -> Text {
+    let origin = Origin {
+        file: 0,
+        location: Location::default(),
+    };
+    text.chars().map(|ch| (ch, origin)).collect()
+}
+
+/// Replace the parameters in `body` with their arguments
+fn substitute(body: &[(char, Origin)], params: &[String], args: &[Text]) -> Text {
+    let mut text = Text::new();
+    let mut i = 0;
+    while i < body.len() {
+        match ident(body, i) {
+            Some(name) => {
+                let len = name.chars().count();
+                match params.iter().position(|param| *param == name) {
+                    Some(param) => text.extend_from_slice(&args[param]),
+                    None => text.extend_from_slice(&body[i..i + len]),
+                }
+                i += len;
+            }
+            None => {
+                text.push(body[i]);
+                i += 1;
+            }
+        }
+    }
+    text
+}
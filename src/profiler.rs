@@ -0,0 +1,219 @@
+//! Execution profiler, counting how many times each command runs.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::location::*;
+use crate::observer::*;
+use crate::program::*;
+
+/// Profile of a single loop
+#[derive(Clone, Debug)]
+pub struct LoopProfile {
+    /// Location of '['
+    pub open: Location,
+    /// Location of ']'
+    pub close: Location,
+    /// Number of times the loop was reached
+    pub entries: u64,
+    /// Number of times the loop body ran, over all entries
+    pub iterations: u64,
+    /// Number of commands executed inside the loop, including its brackets and nested loops
+    pub steps: u64,
+}
+
+/// Loop nesting in which commands ran. Frames form a tree, with frame 0 being the top level of
+/// the program.
+struct Frame {
+    parent: usize,
+    /// Index of the '[' opening the loop
+    open: usize,
+    /// Number of commands executed directly in this frame, excluding nested loops
+    samples: u64,
+}
+
+/// Observer counting how many times each command runs
+pub struct Profiler {
+    program: Program,
+    /// Number of times each command ran
+    pub hits: Vec<u64>,
+    frames: Vec<Frame>,
+    children: HashMap<(usize, usize), usize>,
+    current: usize,
+}
+
+impl Profiler {
+    pub fn new(program: &Program) -> Self {
+        Self {
+            program: program.clone(),
+            hits: vec![0; program.commands.len()],
+            frames: vec![Frame {
+                parent: 0,
+                open: 0,
+                samples: 0,
+            }],
+            children: HashMap::new(),
+            current: 0,
+        }
+    }
+
+    /// Total number of commands executed
+    pub fn total(&self) -> u64 {
+        self.hits.iter().sum()
+    }
+
+    /// Profile of every loop, hottest (most steps) first
+    pub fn loops(&self) -> Vec<LoopProfile> {
+        let mut loops: Vec<LoopProfile> = self
+            .program
+            .commands
+            .iter()
+            .enumerate()
+            .filter_map(|(open, command)| match command {
+                Command::Open(close) => Some(LoopProfile {
+                    open: self.program.locations[open],
+                    close: self.program.locations[*close],
+                    entries: self.hits[open],
+                    iterations: self.hits[*close],
+                    steps: self.hits[open..=*close].iter().sum(),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        loops.sort_by(|a, b| b.steps.cmp(&a.steps).then(a.open.cmp(&b.open)));
+        loops
+    }
+
+    /// Report of the `count` hottest loops
+    pub fn report(&self, count: usize) -> String {
+        let mut report = String::new();
+        let total = self.total();
+
+        writeln!(report, "Total steps: {}", total).unwrap();
+        writeln!(report, "Hottest loops:").unwrap();
+        writeln!(
+            report,
+            "{:>14} {:>7} {:>14} {:>10}  source",
+            "steps", "%", "iterations", "entries"
+        )
+        .unwrap();
+        for profile in self.loops().iter().take(count) {
+            writeln!(
+                report,
+                "{:>14} {:>6.2}% {:>14} {:>10}  {}-{}",
+                profile.steps,
+                percent(profile.steps, total),
+                profile.iterations,
+                profile.entries,
+                profile.open,
+                profile.close
+            )
+            .unwrap();
+        }
+
+        report
+    }
+
+    /// Source code annotated with how many commands ran on each line
+    pub fn annotate(&self, src: &str) -> String {
+        let mut line_hits = vec![None; src.split('\n').count()];
+        for (hits, loc) in self.hits.iter().zip(&self.program.locations) {
+            let line = loc
+                .line
+                .checked_sub(1)
+                .and_then(|line| line_hits.get_mut(line));
+            if let Some(line) = line {
+                *line = Some(line.unwrap_or(0) + hits);
+            }
+        }
+
+        let mut listing = String::new();
+        for (text, hits) in src.split('\n').zip(line_hits) {
+            let text = text.trim_end_matches('\r');
+            match hits {
+                Some(hits) => writeln!(listing, "{:>14} | {}", hits, text).unwrap(),
+                None => writeln!(listing, "{:>14} | {}", "", text).unwrap(),
+            }
+        }
+
+        listing
+    }
+
+    /// Profile in folded stack format, one line per loop nesting with the number of commands
+    /// executed directly in it. Each frame is a loop identified by its source span, e.g.
+    /// `main;[2:1-13:1];[4:2-6:2] 99`. The output can be rendered by flamegraph tools.
+    pub fn folded(&self) -> String {
+        let mut folded = String::new();
+        for (id, frame) in self.frames.iter().enumerate() {
+            if frame.samples == 0 {
+                continue;
+            }
+
+            let mut names = Vec::new();
+            let mut id = id;
+            while id != 0 {
+                let frame = &self.frames[id];
+                let close = match self.program.commands[frame.open] {
+                    Command::Open(close) => close,
+                    _ => frame.open,
+                };
+                names.push(format!(
+                    "[{}-{}]",
+                    self.program.locations[frame.open], self.program.locations[close]
+                ));
+                id = frame.parent;
+            }
+            names.push(String::from("main"));
+            names.reverse();
+
+            writeln!(folded, "{} {}", names.join(";"), frame.samples).unwrap();
+        }
+
+        folded
+    }
+
+    /// Frame for the loop opened at `open` nested in the current frame
+    fn enter(&mut self, open: usize) -> usize {
+        let key = (self.current, open);
+        if let Some(id) = self.children.get(&key) {
+            return *id;
+        }
+
+        self.frames.push(Frame {
+            parent: self.current,
+            open,
+            samples: 0,
+        });
+        self.children.insert(key, self.frames.len() - 1);
+        self.frames.len() - 1
+    }
+}
+
+impl Observer for Profiler {
+    fn observe(&mut self, step: &Step) {
+        self.hits[step.command_ptr] += 1;
+
+        match step.command {
+            // entering the loop, the bracket counts as part of it
+            Command::Open(_) if step.before != 0 => {
+                self.current = self.enter(step.command_ptr);
+                self.frames[self.current].samples += 1;
+            }
+            // leaving the loop, the bracket counts as part of it
+            Command::Close(_) if step.before == 0 => {
+                self.frames[self.current].samples += 1;
+                self.current = self.frames[self.current].parent;
+            }
+            _ => self.frames[self.current].samples += 1,
+        }
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
@@ -1,5 +1,9 @@
+use std::fmt;
+
+use crate::location::*;
+
 /// List of available BF commands. Currently, only a subset of BF commands are supported.
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Command {
     Halt,         // halt program
     Right,        // increment pointer
@@ -12,6 +16,82 @@ pub enum Command {
     Write,        // write to either stdout or file
 }
 
-/// Instructions for the program
-#[derive(Clone)]
-pub struct Program(pub Vec<Command>);
+/// Instructions for the program. Programs are only built by the compiler, from a syntax tree or
+/// from verified bytecode, so every jump lands on its matching bracket and the last command is
+/// `Halt`.
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub(crate) commands: Vec<Command>,
+    /// Location in the source code of each command
+    pub(crate) locations: Vec<Location>,
+}
+
+impl Program {
+    /// Commands of the program, ending with `Halt`
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Location in the source code of each command
+    pub fn locations(&self) -> &[Location] {
+        &self.locations
+    }
+
+    /// Iterate over the commands together with their locations in the source code.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            commands: self.commands.iter(),
+            locations: self.locations.iter(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Program {
+    type Item = (&'a Command, &'a Location);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over the commands of a program together with their locations
+pub struct Iter<'a> {
+    commands: std::slice::Iter<'a, Command>,
+    locations: std::slice::Iter<'a, Location>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a Command, &'a Location);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some((self.commands.next()?, self.locations.next()?))
+    }
+}
+
+/// Display the command as its BF character. `Halt` is displayed as nothing.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Halt => Ok(()),
+            Command::Right => write!(f, ">"),
+            Command::Left => write!(f, "<"),
+            Command::Inc => write!(f, "+"),
+            Command::Dec => write!(f, "-"),
+            Command::Open(_) => write!(f, "["),
+            Command::Close(_) => write!(f, "]"),
+            Command::Read => write!(f, ","),
+            Command::Write => write!(f, "."),
+        }
+    }
+}
+
+/// Display the program as BF source code without comments.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for command in &self.commands {
+            write!(f, "{}", command)?;
+        }
+        Ok(())
+    }
+}
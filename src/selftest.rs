@@ -0,0 +1,252 @@
+//! Conformance suite, run by `brainrust selftest`. Classic portability tests of Brainfuck
+//! interpreters, and probes of the settings, are run under every combination of `dynamic_size`,
+//! `wrapping`, `eof_behavior` and `newline_mode`, and checked against what those settings are
+//! documented to do. `input_mode`, `ignore_newline` and `array_size` are not combined in full:
+//! each combination takes the next of their values in turn, so every value runs with every value
+//! of the four settings above.
+//!
+//! - Cells are 8 bits and wrap around, whatever the settings.
+//! - On EOF, ',' leaves the cell as is, sets it to 0 or sets it to 255, as chosen.
+//! - Input is read as bytes or as decimal numbers, and newlines in it are read or skipped, as
+//!   chosen.
+//! - '<' on the first cell wraps around to the last cell with `wrapping`, and is an out-of-bound
+//!   access otherwise.
+//! - A fixed array has room for the 30000 cells programs expect, and a dynamic array grows from
+//!   the size it starts with.
+//! - Output newlines are written as CRLF or LF, as chosen.
+
+use crate::compiler::*;
+use crate::equivalence::*;
+use crate::generator::*;
+use crate::program::*;
+use crate::settings::*;
+use crate::spec::*;
+
+/// Tests the cell size, and prints "8 bit cells". By Brian Raiter.
+const CELL_SIZE: &str = r#"
+Calculate the value 256 and test if it's zero
+If the interpreter errors on overflow this is where it'll happen
+++++++++[>++++++++<-]>[<++++>-]
++<[>-<
+    Not zero so multiply by 256 again to get 65536
+    [>++++<-]>[<++++++++>-]<[>++++++++<-]
+    +>[>
+        # Print "32"
+        ++++++++++[>+++++<-]>+.-.[-]<
+    <[-]<->] <[>>
+        # Print "16"
+        +++++++[>+++++++<-]>.+++++.[-]<
+<<-]] >[>
+    # Print "8"
+    ++++++++[>+++++++<-]>.[-]<
+<-]<
+# Print " bit cells\n"
++++++++++++[>+++>+++++++++>+++++++++>+<<<<-]>-.>-.+++++++.+++++++++++.<.
+>>.++.+++++++..<-.>>-
+Clean up used cells.
+[[-]<]
+"#;
+
+/// Reads a newline, then EOF into a cell holding 9, and prints "LK" twice if the cell is left as
+/// is, "LB" if it is set to 0 and "LA" if it is set to -1. By Daniel B. Cristofani. The newline is
+/// given as the number 10 in digit mode, and after a skipped newline with `ignore_newline`.
+const EOF: &str = ">,>+++++++++,>+++++++++++[<++++++<++++++<+>>>-]<<.>.<<-.>.>.<<.";
+
+/// Skips a loop starting with "]" characters in comments, and other brackets parsers get wrong,
+/// and prints "H". By Daniel B. Cristofani.
+const OBSCURE: &str = r#"
+[]++++++++++[>>+>+>++++++[<<+<+++>>>-]<<<<-]
+"A*$";?@![#>>+<<]>[>>]<<<<[>++<[-]]>.>.
+"#;
+
+/// Goes to cell 30000 and prints "#" from there. By Daniel B. Cristofani.
+const TAPE_LENGTH: &str = concat!(
+    "++++[>++++++<-]>[>+++++>+++++++<<-]>>++++<[[>[[>>+<<-]<]>>>-]>-[>+>+<<-]>]",
+    "+++++[>+++++++<<++>-]>.<<.",
+);
+
+/// Number of commands a conformance test runs before it is stopped. The tape length test runs
+/// about 18 million.
+const SELFTEST_STEPS: u64 = 100_000_000;
+
+/// Loop nesting of the bracket stress test
+const DEPTH: usize = 200;
+
+/// Conformance test: a program, and what it is expected to do under some settings
+pub struct Conformance {
+    pub name: &'static str,
+    pub program: Program,
+    /// Input under the settings
+    pub input: fn(&Settings) -> Vec<u8>,
+    /// Expected output and result under the settings
+    pub expected: fn(&Settings) -> (Vec<u8>, Ending),
+}
+
+/// Conformance test that failed under some settings
+pub struct Nonconformance {
+    pub name: &'static str,
+    pub settings: Settings,
+    pub failures: Vec<Failure>,
+}
+
+/// Every conformance test.
+pub fn conformance_suite() -> Vec<Conformance> {
+    // nested loops that run once, then nested loops skipped, then "OK"
+    let brackets = format!(
+        "+{}-{}>{}{}{}",
+        "[".repeat(DEPTH),
+        "]".repeat(DEPTH),
+        "[".repeat(DEPTH),
+        "]".repeat(DEPTH),
+        generate_text(b"OK\n", &TextOptions::default())
+    );
+    // prints "W" from the cell left of the first one, or "X" if that is the first one again
+    let left_edge = format!("+<{}.[-]{}.", "+".repeat(b'W' as usize), "+".repeat(10));
+
+    let compile = |src: &str| Compiler::new().compile(src.to_string()).unwrap();
+    vec![
+        Conformance {
+            name: "cell size",
+            program: compile(CELL_SIZE),
+            input: |_| Vec::new(),
+            expected: |settings| (text(b"8 bit cells\n", settings), Ending::Halted),
+        },
+        Conformance {
+            name: "eof",
+            program: compile(EOF),
+            input: |settings| match (&settings.input_mode, settings.ignore_newline) {
+                (InputMode::Digit, _) => text(b"10\n", settings),
+                (InputMode::Ascii, false) => text(b"\n", settings),
+                (InputMode::Ascii, true) => [text(b"\n", settings), b"\n".to_vec()].concat(),
+            },
+            expected: |settings| {
+                let output = match settings.eof_behavior {
+                    EofBehavior::AsIs => b"LK\nLK\n",
+                    EofBehavior::Zero => b"LB\nLB\n",
+                    EofBehavior::NegativeOne => b"LA\nLA\n",
+                };
+                (text(output, settings), Ending::Halted)
+            },
+        },
+        Conformance {
+            name: "obscure brackets",
+            program: compile(OBSCURE),
+            input: |_| Vec::new(),
+            expected: |settings| (text(b"H\n", settings), Ending::Halted),
+        },
+        Conformance {
+            name: "bracket stress",
+            program: compile(&brackets),
+            input: |_| Vec::new(),
+            expected: |settings| (text(b"OK\n", settings), Ending::Halted),
+        },
+        Conformance {
+            name: "tape length",
+            program: compile(TAPE_LENGTH),
+            input: |_| Vec::new(),
+            expected: |settings| (text(b"#\n", settings), Ending::Halted),
+        },
+        Conformance {
+            name: "left edge",
+            program: compile(&left_edge),
+            input: |_| Vec::new(),
+            expected: |settings| {
+                if settings.wrapping && settings.array_size == 1 {
+                    (text(b"X\n", settings), Ending::Halted)
+                } else if settings.wrapping {
+                    (text(b"W\n", settings), Ending::Halted)
+                } else {
+                    (Vec::new(), Ending::IndexOutOfBound)
+                }
+            },
+        },
+    ]
+}
+
+/// Every combination of the settings checked. A fixed array has 30000 or 65536 cells, and a
+/// dynamic array starts with 1 or 100 cells.
+pub fn conformance_settings() -> Vec<Settings> {
+    let mut combinations = Vec::new();
+    for (dynamic, &dynamic_size) in [false, true].iter().enumerate() {
+        for (wrap, &wrapping) in [false, true].iter().enumerate() {
+            for (eof, eof_behavior) in [
+                EofBehavior::AsIs,
+                EofBehavior::Zero,
+                EofBehavior::NegativeOne,
+            ]
+            .iter()
+            .enumerate()
+            {
+                for (newline, newline_mode) in
+                    [NewlineMode::CRLF, NewlineMode::LF].iter().enumerate()
+                {
+                    // the sum takes every value mod 4 when any one of the four settings is fixed
+                    let turn = dynamic + wrap + eof + newline;
+                    let sizes = if dynamic_size {
+                        [1, 100]
+                    } else {
+                        [30000, 65536]
+                    };
+                    combinations.push(Settings {
+                        dynamic_size,
+                        array_size: sizes[(wrap + eof + newline) % 2],
+                        eof_behavior: eof_behavior.clone(),
+                        newline_mode: newline_mode.clone(),
+                        ignore_newline: turn % 2 == 1,
+                        input_mode: if turn % 4 < 2 {
+                            InputMode::Ascii
+                        } else {
+                            InputMode::Digit
+                        },
+                        wrapping,
+                    });
+                }
+            }
+        }
+    }
+    combinations
+}
+
+/// Run every conformance test under every combination of settings. Return the ones failing, and
+/// the number of runs.
+pub fn selftest() -> (Vec<Nonconformance>, usize) {
+    let mut nonconformances = Vec::new();
+    let mut runs = 0;
+    for test in conformance_suite() {
+        for settings in conformance_settings() {
+            let (output, result) = (test.expected)(&settings);
+            let case = TestCase {
+                name: test.name.to_string(),
+                input: (test.input)(&settings),
+                output: Some(output),
+                tape: None,
+                result,
+            };
+
+            runs += 1;
+            let failures = run_case(&test.program, &settings, SELFTEST_STEPS, &case);
+            if !failures.is_empty() {
+                nonconformances.push(Nonconformance {
+                    name: test.name,
+                    settings,
+                    failures,
+                });
+            }
+        }
+    }
+    (nonconformances, runs)
+}
+
+/// `text` with newlines written as the settings choose
+fn text(text: &[u8], settings: &Settings) -> Vec<u8> {
+    match settings.newline_mode {
+        NewlineMode::CRLF => text
+            .iter()
+            .flat_map(|byte| match byte {
+                b'\n' => b"\r\n".to_vec(),
+                byte => vec![*byte],
+            })
+            .collect(),
+        NewlineMode::LF => text.to_vec(),
+    }
+}
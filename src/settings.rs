@@ -30,4 +30,18 @@ pub struct Settings {
     pub ignore_newline: bool,
     pub input_mode: InputMode,
     pub wrapping: bool,
-}
\ No newline at end of file
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            dynamic_size: false,
+            array_size: 30000,
+            eof_behavior: EofBehavior::AsIs,
+            newline_mode: NewlineMode::CRLF,
+            ignore_newline: false,
+            input_mode: InputMode::Ascii,
+            wrapping: false,
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Test specs. A program `name.bf` is tested by the cases listed in `name.toml` next to it:
+//!
+//! ```toml
+//! [settings]
+//! array_size = 10
+//! eof_behavior = "zero"
+//!
+//! [[case]]
+//! name = "reverses its input"
+//! input = "abc"
+//! output = "cba"
+//! tape = [0, 99]
+//!
+//! [[case]]
+//! input = [255, 0]
+//! result = "index_out_of_bound"
+//! ```
+//!
+//! Settings have the names and values of the command-line options, and default like them, except
+//! that newlines are not ignored. `max_steps` limits how many commands run (default: 10000000).
+//!
+//! Every field of a case is optional:
+//! - `input` is a string or an array of bytes. Default: empty.
+//! - `output` is the expected output, a string or an array of bytes.
+//! - `tape` is the expected values of the first cells when the program stops.
+//! - `result` is how the program is expected to stop: `halted` (default), `index_out_of_bound`,
+//!   `read_failed`, `write_failed`, `parse_num_error`, `infinite_loop` or `step_limit`.
+//!
+//! Infinite loops are detected, see `loop_detector`.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use serde::Deserialize;
+
+use crate::equivalence::*;
+use crate::error::*;
+use crate::interpreter_builder::*;
+use crate::program::*;
+use crate::settings::*;
+
+/// Default number of commands a case runs before it is stopped
+pub const SPEC_STEPS: u64 = 10_000_000;
+
+/// Test case of a spec
+#[derive(Clone, Debug)]
+pub struct TestCase {
+    pub name: String,
+    pub input: Vec<u8>,
+    pub output: Option<Vec<u8>>,
+    pub tape: Option<Vec<u8>>,
+    pub result: Ending,
+}
+
+/// Settings and test cases of a program
+#[derive(Clone)]
+pub struct Spec {
+    pub settings: Settings,
+    pub max_steps: u64,
+    pub cases: Vec<TestCase>,
+}
+
+/// Difference between what a case expects and what the program did
+#[derive(Clone, Debug)]
+pub enum Failure {
+    Output {
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    /// `actual` holds as many cells as `expected`
+    Tape {
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    Result {
+        expected: Ending,
+        actual: Ending,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpecFile {
+    #[serde(default)]
+    settings: SettingsFile,
+    #[serde(default)]
+    case: Vec<CaseFile>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsFile {
+    dynamic_size: Option<bool>,
+    array_size: Option<usize>,
+    wrapping: Option<bool>,
+    eof_behavior: Option<String>,
+    newline_mode: Option<String>,
+    input_mode: Option<String>,
+    ignore_newline: Option<bool>,
+    max_steps: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CaseFile {
+    name: Option<String>,
+    input: Option<Bytes>,
+    output: Option<Bytes>,
+    tape: Option<Vec<u8>>,
+    result: Option<String>,
+}
+
+/// Bytes written as a string or an array
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Bytes {
+    Text(String),
+    Array(Vec<u8>),
+}
+
+impl From<Bytes> for Vec<u8> {
+    fn from(bytes: Bytes) -> Self {
+        match bytes {
+            Bytes::Text(text) => text.into_bytes(),
+            Bytes::Array(array) => array,
+        }
+    }
+}
+
+impl Spec {
+    /// Parse a spec written in TOML.
+    pub fn parse(src: &str) -> Result<Spec, SpecError> {
+        let file: SpecFile = toml::from_str(src).map_err(|err| SpecError::Toml(err.to_string()))?;
+
+        let defaults = Settings::default();
+        let settings = file.settings;
+        let eof_behavior = match settings.eof_behavior.as_deref() {
+            Some("as_is") => EofBehavior::AsIs,
+            Some("negative_one") => EofBehavior::NegativeOne,
+            Some("zero") => EofBehavior::Zero,
+            Some(other) => return Err(SpecError::InvalidSetting("eof_behavior", other.into())),
+            None => defaults.eof_behavior,
+        };
+        let newline_mode = match settings.newline_mode.as_deref() {
+            Some("CRLF") => NewlineMode::CRLF,
+            Some("LF") => NewlineMode::LF,
+            Some(other) => return Err(SpecError::InvalidSetting("newline_mode", other.into())),
+            None => defaults.newline_mode,
+        };
+        let input_mode = match settings.input_mode.as_deref() {
+            Some("ascii") => InputMode::Ascii,
+            Some("digit") => InputMode::Digit,
+            Some(other) => return Err(SpecError::InvalidSetting("input_mode", other.into())),
+            None => defaults.input_mode,
+        };
+        let cases = file
+            .case
+            .into_iter()
+            .enumerate()
+            .map(|(i, case)| {
+                let result = match case.result.as_deref() {
+                    Some(result) => Ending::parse(result)
+                        .ok_or_else(|| SpecError::InvalidResult(result.to_string()))?,
+                    None => Ending::Halted,
+                };
+                Ok(TestCase {
+                    name: case.name.unwrap_or_else(|| format!("case {}", i + 1)),
+                    input: case.input.map_or_else(Vec::new, Vec::from),
+                    output: case.output.map(Vec::from),
+                    tape: case.tape,
+                    result,
+                })
+            })
+            .collect::<Result<_, SpecError>>()?;
+
+        Ok(Spec {
+            settings: Settings {
+                dynamic_size: settings.dynamic_size.unwrap_or(defaults.dynamic_size),
+                array_size: settings.array_size.unwrap_or(defaults.array_size),
+                eof_behavior,
+                newline_mode,
+                ignore_newline: settings.ignore_newline.unwrap_or(false),
+                input_mode,
+                wrapping: settings.wrapping.unwrap_or(defaults.wrapping),
+            },
+            max_steps: settings.max_steps.unwrap_or(SPEC_STEPS),
+            cases,
+        })
+    }
+
+    /// Run `case` on `program` with the settings of the spec, see `run_case`.
+    pub fn run(&self, program: &Program, case: &TestCase) -> Vec<Failure> {
+        run_case(program, &self.settings, self.max_steps, case)
+    }
+}
+
+/// Run `case` on `program` with `settings` for at most `max_steps` commands, and return how it
+/// failed. Empty means it passed.
+pub fn run_case(
+    program: &Program,
+    settings: &Settings,
+    max_steps: u64,
+    case: &TestCase,
+) -> Vec<Failure> {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = InterpreterBuilder::from_program(program.clone(), settings.clone())
+        .input(case.input.clone())
+        .output(output.clone())
+        .detect_loops()
+        .build()
+        .unwrap();
+
+    let mut state = interpreter.ready();
+    let ending = interpreter.run_for(&mut state, max_steps).into();
+
+    let mut failures = Vec::new();
+    let output = output.borrow();
+    if let Some(expected) = &case.output {
+        if *expected != *output {
+            failures.push(Failure::Output {
+                expected: expected.clone(),
+                actual: output.clone(),
+            });
+        }
+    }
+    if let Some(expected) = &case.tape {
+        let mut actual = state.cells.0;
+        actual.resize(expected.len(), 0);
+        if *expected != actual {
+            failures.push(Failure::Tape {
+                expected: expected.clone(),
+                actual,
+            });
+        }
+    }
+    if case.result != ending {
+        failures.push(Failure::Result {
+            expected: case.result,
+            actual: ending,
+        });
+    }
+    failures
+}
+
+impl Ending {
+    fn parse(name: &str) -> Option<Ending> {
+        match name {
+            "halted" => Some(Ending::Halted),
+            "index_out_of_bound" => Some(Ending::IndexOutOfBound),
+            "read_failed" => Some(Ending::ReadFailed),
+            "write_failed" => Some(Ending::WriteFailed),
+            "parse_num_error" => Some(Ending::ParseNumError),
+            "infinite_loop" => Some(Ending::InfiniteLoop),
+            "step_limit" => Some(Ending::StepLimit),
+            _ => None,
+        }
+    }
+}
+
+/// Display the name used in spec files.
+impl fmt::Display for Ending {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Ending::Halted => "halted",
+            Ending::IndexOutOfBound => "index_out_of_bound",
+            Ending::ReadFailed => "read_failed",
+            Ending::WriteFailed => "write_failed",
+            Ending::ParseNumError => "parse_num_error",
+            Ending::InfiniteLoop => "infinite_loop",
+            Ending::StepLimit => "step_limit",
+        };
+        write!(f, "{}", name)
+    }
+}
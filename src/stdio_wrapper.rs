@@ -1,8 +1,10 @@
 //! Since `Stdin` does not lock on `Read::read`, we have to create a wrapper of stdin
 //! that automatically lock on read. While at it, we also create a wrapper of stdout
-//! for conformity.
+//! for conformity. Output can also go to a buffer shared with the caller.
 
+use std::cell::RefCell;
 use std::io::prelude::*;
+use std::rc::Rc;
 
 /// Wrapper around Stdin that automatically lock on read.
 pub struct StdinWrapper {
@@ -45,3 +47,25 @@ impl Write for StdoutWrapper {
         self.stdout.flush()
     }
 }
+
+/// Writer appending to a buffer that the caller keeps a handle to.
+pub struct SharedBuffer {
+    buf: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SharedBuffer {
+    pub fn new(buf: Rc<RefCell<Vec<u8>>>) -> Self {
+        Self { buf }
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
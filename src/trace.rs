@@ -0,0 +1,105 @@
+//! Execution trace, recording every executed command.
+//!
+//! Two formats are available:
+//!
+//! - JSON Lines: one JSON object per step, e.g.
+//!   `{"step":0,"command_ptr":0,"command":"+","line":1,"column":1,"cell_ptr":0,"before":0,"after":1}`
+//! - Binary: the magic number `BFT\0` and a 2-byte version (currently 1), followed by one
+//!   43-byte record per step. Records are made of step, command pointer, line, column and cell
+//!   pointer (8 bytes each), command character, value before and value after (1 byte each). All
+//!   integers are little-endian. The cell pointer is past the end of the array, e.g. `u64::MAX`,
+//!   when a command moves it there, as `<` does on the first cell without wrapping.
+
+use std::io::prelude::*;
+
+use crate::observer::*;
+
+/// Magic number at the start of every binary trace
+pub const TRACE_MAGIC: &[u8; 4] = b"BFT\0";
+
+/// Current version of the binary trace format
+pub const TRACE_VERSION: u16 = 1;
+
+/// Format of the trace
+#[derive(Clone)]
+pub enum TraceFormat {
+    JsonLines,
+    Binary,
+}
+
+/// Observer that writes every step to `writer`
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    format: TraceFormat,
+    /// First error while writing. Observers can't fail, so it is reported in `finish`.
+    error: Option<std::io::Error>,
+}
+
+impl Tracer {
+    pub fn new(writer: Box<dyn Write>, format: TraceFormat) -> Self {
+        let mut tracer = Self {
+            writer,
+            format,
+            error: None,
+        };
+
+        if let TraceFormat::Binary = tracer.format {
+            let mut header = TRACE_MAGIC.to_vec();
+            header.extend_from_slice(&TRACE_VERSION.to_le_bytes());
+            tracer.write(&header);
+        }
+
+        tracer
+    }
+
+    fn write(&mut self, buf: &[u8]) {
+        if self.error.is_none() {
+            if let Err(err) = self.writer.write_all(buf) {
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+impl Observer for Tracer {
+    fn observe(&mut self, step: &Step) {
+        let record = match self.format {
+            TraceFormat::JsonLines => format!(
+                "{{\"step\":{},\"command_ptr\":{},\"command\":\"{}\",\"line\":{},\"column\":{},\"cell_ptr\":{},\"before\":{},\"after\":{}}}\n",
+                step.step,
+                step.command_ptr,
+                step.command,
+                step.location.line,
+                step.location.column,
+                step.cell_ptr,
+                step.before,
+                step.after
+            )
+            .into_bytes(),
+            TraceFormat::Binary => {
+                let mut record = Vec::with_capacity(43);
+                for val in [
+                    step.step,
+                    step.command_ptr as u64,
+                    step.location.line as u64,
+                    step.location.column as u64,
+                    step.cell_ptr as u64,
+                ] {
+                    record.extend_from_slice(&val.to_le_bytes());
+                }
+                record.push(step.command.to_string().bytes().next().unwrap_or(0));
+                record.push(step.before);
+                record.push(step.after);
+                record
+            }
+        };
+        self.write(&record);
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,114 @@
+//! Conversions between programs and their syntax tree, and visitors over the tree.
+
+use brainrust::*;
+
+fn compile(src: &str) -> Program {
+    Compiler::new().compile(src.to_string()).unwrap()
+}
+
+/// Assert `src` is rebuilt from its syntax tree with the same commands and locations. The tree
+/// doesn't keep where the source ends, so the location of the final halt is not compared.
+fn assert_round_trip(src: &str) {
+    let program = compile(src);
+    let rebuilt = Program::from(&Block::from(&program));
+    assert_eq!(rebuilt.commands(), program.commands(), "{}", src);
+    let len = program.locations().len() - 1;
+    assert_eq!(
+        rebuilt.locations()[..len],
+        program.locations()[..len],
+        "{}",
+        src
+    );
+}
+
+#[test]
+fn round_trip() {
+    for src in &[
+        "",
+        "+",
+        "[]",
+        "[[]]",
+        "+[-]>[<]",
+        "[[>]<[+[-]]],.",
+        "a [b] c\n[\n]",
+    ] {
+        assert_round_trip(src);
+    }
+}
+
+#[test]
+fn round_trip_examples() {
+    for example in &["cat.txt", "hello_world.txt", "multiply.txt", "reverse.bf"] {
+        let path = format!("{}/examples/{}", env!("CARGO_MANIFEST_DIR"), example);
+        assert_round_trip(&std::fs::read_to_string(path).unwrap());
+    }
+}
+
+#[test]
+fn loops_are_nested() {
+    let block = Block::from(&compile("+[->[-]<]."));
+    let nodes = &block.0;
+    assert_eq!(nodes.len(), 3);
+    assert!(matches!(nodes[0], Node::Inc(_)));
+    assert!(matches!(nodes[2], Node::Write(_)));
+
+    let (body, open, close) = match &nodes[1] {
+        Node::Loop(body, open, close) => (body, open, close),
+        node => panic!("expected a loop, got {:?}", node),
+    };
+    assert_eq!((open.index, close.index), (1, 8));
+    assert_eq!(body.0.len(), 4);
+    match &body.0[2] {
+        Node::Loop(inner, open, _) => {
+            assert_eq!(open.index, 4);
+            assert!(matches!(inner.0[..], [Node::Dec(_)]));
+        }
+        node => panic!("expected a loop, got {:?}", node),
+    }
+}
+
+/// Counts loops by their depth
+#[derive(Default)]
+struct Depths {
+    depth: usize,
+    loops: Vec<usize>,
+}
+
+impl Visitor for Depths {
+    fn visit_node(&mut self, node: &Node) {
+        if let Node::Loop(..) = node {
+            self.loops.push(self.depth);
+            self.depth += 1;
+            walk_node(self, node);
+            self.depth -= 1;
+        }
+    }
+}
+
+#[test]
+fn visitor_descends_into_loops() {
+    let mut depths = Depths::default();
+    Block::from(&compile("[[][[]]]>[]")).accept(&mut depths);
+    assert_eq!(depths.loops, [0, 1, 1, 2, 0]);
+}
+
+/// Swaps '+' and '-'
+struct Negate;
+
+impl VisitorMut for Negate {
+    fn visit_node_mut(&mut self, node: &mut Node) {
+        match *node {
+            Node::Inc(loc) => *node = Node::Dec(loc),
+            Node::Dec(loc) => *node = Node::Inc(loc),
+            _ => walk_node_mut(self, node),
+        }
+    }
+}
+
+#[test]
+fn visitor_mut_changes_the_program() {
+    let mut block = Block::from(&compile("+[-[+]]"));
+    block.accept_mut(&mut Negate);
+    let program = Program::from(&block);
+    assert_eq!(program.commands(), compile("-[+[-]]").commands());
+}
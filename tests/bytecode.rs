@@ -0,0 +1,111 @@
+//! Encoding programs into bytecode and decoding them back.
+
+use brainrust::*;
+
+#[test]
+fn round_trip() {
+    let program = Compiler::new().compile("+[->,.<]\n>".to_string()).unwrap();
+    let settings = Settings {
+        wrapping: true,
+        eof_behavior: EofBehavior::Zero,
+        ..Settings::default()
+    };
+
+    let (decoded, decoded_settings) = decode(&encode(&program, &settings).unwrap()).unwrap();
+    assert_eq!(decoded.commands(), program.commands());
+    assert_eq!(decoded.locations(), program.locations());
+    assert!(decoded_settings.wrapping);
+    assert!(matches!(decoded_settings.eof_behavior, EofBehavior::Zero));
+}
+
+#[test]
+fn corruption_is_detected() {
+    let program = Compiler::new().compile("+.".to_string()).unwrap();
+    let mut bytes = encode(&program, &Settings::default()).unwrap();
+    bytes[20] ^= 1;
+    assert!(matches!(
+        decode(&bytes),
+        Err(BytecodeError::ChecksumMismatch)
+    ));
+}
+
+#[test]
+fn locations_too_large_are_rejected() {
+    let loc = Location {
+        index: u32::MAX as usize + 1,
+        line: 1,
+        column: 1,
+    };
+    let program = Program::from(&Block(vec![Node::Inc(loc)]));
+    let result = encode(&program, &Settings::default());
+    assert!(matches!(result, Err(BytecodeError::TooLarge)));
+}
+
+#[test]
+fn runs_are_stored_once() {
+    let program = Compiler::new().compile("+++++\n>>>>>".to_string()).unwrap();
+    let bytes = encode(&program, &Settings::default()).unwrap();
+    // header, 3 ops (2 runs and halt), a source map entry for each of the 11 commands, checksum
+    assert_eq!(bytes.len(), 18 + 4 + (5 + 5 + 1) + 11 * 12 + 4);
+
+    let (decoded, _) = decode(&bytes).unwrap();
+    assert_eq!(decoded.commands(), program.commands());
+    assert_eq!(decoded.locations(), program.locations());
+}
+
+/// 32-bit FNV-1a hash, as used for the checksum
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Bytecode with the given `ops`, a source map of `commands` entries and a valid checksum
+fn bytecode(count: u32, ops: &[u8], commands: usize) -> Vec<u8> {
+    let empty = Compiler::new().compile(String::new()).unwrap();
+    let mut bytes = encode(&empty, &Settings::default()).unwrap();
+    // keep the header up to the op count
+    bytes.truncate(18);
+    bytes.extend_from_slice(&count.to_le_bytes());
+    bytes.extend_from_slice(ops);
+    bytes.resize(bytes.len() + commands * 12, 0);
+    let checksum = fnv1a(&bytes);
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+    bytes
+}
+
+#[test]
+fn halt_is_only_allowed_at_the_end() {
+    // '+', halt, halt
+    let bytes = bytecode(3, &[3, 1, 0, 0, 0, 0, 0], 3);
+    assert!(matches!(
+        decode(&bytes),
+        Err(BytecodeError::InvalidCommand(1))
+    ));
+
+    // '+' without halt
+    let bytes = bytecode(1, &[3, 1, 0, 0, 0], 1);
+    assert!(matches!(decode(&bytes), Err(BytecodeError::MissingHalt)));
+}
+
+#[test]
+fn invalid_ops_are_rejected() {
+    // empty run of '+'
+    let bytes = bytecode(2, &[3, 0, 0, 0, 0, 0], 1);
+    assert!(matches!(
+        decode(&bytes),
+        Err(BytecodeError::InvalidCommand(0))
+    ));
+
+    // '[' jumping to itself
+    let bytes = bytecode(2, &[5, 0, 0, 0, 0, 0], 2);
+    assert!(matches!(decode(&bytes), Err(BytecodeError::InvalidJump(0))));
+
+    // source map longer or shorter than the expanded commands
+    assert!(matches!(
+        decode(&bytecode(1, &[0], 2)),
+        Err(BytecodeError::TrailingData)
+    ));
+    let bytes = bytecode(2, &[3, 0xff, 0xff, 0xff, 0xff, 0], 2);
+    assert!(matches!(decode(&bytes), Err(BytecodeError::Truncated)));
+}
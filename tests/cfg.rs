@@ -0,0 +1,87 @@
+//! Control-flow graphs of nested loops.
+
+use brainrust::*;
+
+fn cfg(src: &str) -> Cfg {
+    let program = Compiler::new().compile(src.to_string()).unwrap();
+    Cfg::new(&program)
+}
+
+/// Target and kind of every edge of every block
+fn edges(cfg: &Cfg) -> Vec<Vec<(usize, EdgeKind)>> {
+    cfg.blocks
+        .iter()
+        .map(|block| {
+            block
+                .edges
+                .iter()
+                .map(|edge| (edge.to, edge.kind))
+                .collect()
+        })
+        .collect()
+}
+
+/// Whether each block can be reached from the first one
+fn reachable(cfg: &Cfg) -> Vec<bool> {
+    let mut reached = vec![false; cfg.blocks.len()];
+    let mut stack = vec![0];
+    while let Some(block) = stack.pop() {
+        if !reached[block] {
+            reached[block] = true;
+            stack.extend(cfg.blocks[block].edges.iter().map(|edge| edge.to));
+        }
+    }
+    reached
+}
+
+#[test]
+fn nested_loops() {
+    use EdgeKind::*;
+
+    let cfg = cfg("+[>[-]<-].");
+    let spans: Vec<(usize, usize)> = cfg
+        .blocks
+        .iter()
+        .map(|block| (block.start, block.end))
+        .collect();
+    assert_eq!(spans, [(0, 1), (2, 3), (4, 5), (6, 8), (9, 10)]);
+    assert_eq!(
+        edges(&cfg),
+        [
+            vec![(1, Enter), (4, Skip)],
+            vec![(2, Enter), (3, Skip)],
+            vec![(2, Repeat), (3, Exit)],
+            vec![(1, Repeat), (4, Exit)],
+            vec![],
+        ]
+    );
+    assert_eq!(reachable(&cfg), [true; 5]);
+}
+
+#[test]
+fn every_block_is_reachable() {
+    for src in ["", "[]", "[[[]]]", "+[[-]>[<]+]", "[>[>[-]<]<]>[.]"] {
+        let cfg = cfg(src);
+        assert!(reachable(&cfg).iter().all(|reached| *reached), "{}", src);
+
+        let halt = cfg.blocks.len() - 1;
+        assert!(cfg.blocks[halt].edges.is_empty(), "{}", src);
+        assert!(
+            cfg.blocks[..halt]
+                .iter()
+                .all(|block| block.edges.len() == 2),
+            "{}",
+            src
+        );
+    }
+}
+
+#[test]
+fn back_edges_only_repeat_loops() {
+    let cfg = cfg("[>[-]<]");
+    for (i, block) in cfg.blocks.iter().enumerate() {
+        for edge in &block.edges {
+            assert_eq!(edge.to <= i, edge.kind == EdgeKind::Repeat);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Command-line options, run through the binary.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Write `contents` to a file named `name` in the temporary directory
+fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("brainrust-cli-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn brainrust(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_brainrust"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Run the program `src` with `args`
+fn run(name: &str, src: &str, args: &[&str]) -> Output {
+    let source = temp_file(name, src.as_bytes());
+    let mut all = vec![source.to_str().unwrap()];
+    all.extend_from_slice(args);
+    let output = brainrust(&all);
+    std::fs::remove_file(&source).unwrap();
+    output
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn input_hex_with_and_without_spaces() {
+    for hex in ["48 69", "4869", " 4 8\t69 "] {
+        let output = run(
+            "input_hex.bf",
+            ",>,",
+            &["--input_hex", hex, "-s", "2", "--final_array"],
+        );
+        assert_eq!(stdout(&output), "\nFinal array: [72, 105]\n", "{:?}", hex);
+    }
+}
+
+#[test]
+fn invalid_hex_is_reported() {
+    for hex in ["486", "4g", "é1"] {
+        let output = run("invalid_hex.bf", ",.", &["--input_hex", hex]);
+        assert_eq!(stdout(&output), "");
+        assert_eq!(
+            stderr(&output).trim(),
+            format!("Failed to parse input_hex: \"{}\"", hex)
+        );
+
+        let output = run("invalid_tape.bf", ".", &["--tape_init", hex]);
+        assert_eq!(
+            stderr(&output).trim(),
+            format!("Failed to parse tape_init: \"{}\"", hex)
+        );
+    }
+}
+
+#[test]
+fn tape_init_sets_first_cells() {
+    let output = run(
+        "tape_init.bf",
+        ">+",
+        &["--tape_init", "01 ff 10", "-s", "4", "--final_array"],
+    );
+    assert_eq!(stdout(&output), "\nFinal array: [1, 0, 16, 0]\n");
+}
+
+#[test]
+fn tape_init_file_is_read_as_bytes() {
+    let tape = temp_file("tape_init_file", &[7, 8]);
+    let output = run(
+        "tape_init_file.bf",
+        "+",
+        &[
+            "--tape_init_file",
+            tape.to_str().unwrap(),
+            "-s",
+            "3",
+            "--final_array",
+        ],
+    );
+    std::fs::remove_file(&tape).unwrap();
+    assert_eq!(stdout(&output), "\nFinal array: [8, 8, 0]\n");
+
+    let output = run(
+        "missing_tape_file.bf",
+        "+",
+        &["--tape_init_file", "no/such/tape"],
+    );
+    assert_eq!(
+        stderr(&output).trim(),
+        "Failed to read tape_init_file: \"no/such/tape\""
+    );
+}
+
+#[test]
+fn longer_tape_extends_the_array() {
+    let output = run(
+        "long_tape.bf",
+        ">>+",
+        &["--tape_init", "01 02 03", "-s", "1", "--final_array"],
+    );
+    assert_eq!(stderr(&output), "");
+    assert_eq!(stdout(&output), "\nFinal array: [1, 2, 4]\n");
+}
+
+#[test]
+fn tape_init_options_conflict() {
+    let output = run(
+        "conflict.bf",
+        "+",
+        &["--tape_init", "00", "--tape_init_file", "tape"],
+    );
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("cannot be used with"));
+}
+
+#[test]
+fn strict_syntax_with_comment_character() {
+    let src = "+ ;\n> %\n";
+    let output = run("strict_semicolon.bf", src, &["--strict", "--comment", ";"]);
+    assert_eq!(stderr(&output).trim(), "Invalid syntax at 2:3: '%'");
+
+    let output = run("strict_percent.bf", src, &["--strict", "--comment", "%"]);
+    assert_eq!(stderr(&output).trim(), "Invalid syntax at 1:3: ';'");
+
+    let output = run("strict_default.bf", "+ # comment\n-", &["--strict"]);
+    assert_eq!(stderr(&output), "");
+
+    let output = run("unmatched.bf", "+[\n  -\n.]]", &[]);
+    assert_eq!(
+        stderr(&output).trim(),
+        "Cannot find matching '[' for ']' at 3:3"
+    );
+
+    let output = run("strict_bad.bf", "+", &["--strict", "--comment", "ab"]);
+    assert_eq!(stderr(&output).trim(), "Failed to parse comment: \"ab\"");
+}
+
+#[test]
+fn bytecode_settings_are_used_unless_overridden() {
+    let source = temp_file("settings.bf", b"<+>>+");
+    let bytecode = temp_file("settings.bfc", b"");
+    let (source, bytecode) = (source.to_str().unwrap(), bytecode.to_str().unwrap());
+    let output = brainrust(&["compile", source, "-o", bytecode, "-s", "2", "--wrapping"]);
+    assert_eq!(stderr(&output), "");
+
+    let output = brainrust(&[bytecode, "--final_array"]);
+    assert_eq!(stdout(&output), "\nFinal array: [0, 2]\n");
+
+    let output = brainrust(&[bytecode, "--no_wrapping", "--final_array"]);
+    assert!(stderr(&output).starts_with("Index out of bound"));
+
+    std::fs::write(source, ">+").unwrap();
+    let output = brainrust(&["compile", source, "-o", bytecode, "-s", "1", "-d"]);
+    assert_eq!(stderr(&output), "");
+    let output = brainrust(&[bytecode, "--final_array"]);
+    assert_eq!(stdout(&output), "\nFinal array: [0, 1]\n");
+    let output = brainrust(&[bytecode, "--fixed_size", "--final_array"]);
+    assert!(stderr(&output).starts_with("Index out of bound"));
+
+    std::fs::remove_file(source).unwrap();
+    std::fs::remove_file(bytecode).unwrap();
+}
@@ -0,0 +1,107 @@
+//! Compiling source code, in the default and strict syntax.
+
+use brainrust::*;
+
+/// Number of commands compiled from `src`, in strict syntax if `comment` is given
+fn compile(src: &str, comment: Option<char>) -> Result<usize, Vec<CompileError>> {
+    let compiler = match comment {
+        Some(comment) => Compiler::new().strict(comment),
+        None => Compiler::new(),
+    };
+    compiler
+        .compile(src.to_string())
+        .map(|program| program.commands().len())
+}
+
+/// Syntax errors of compiling `src` in strict syntax with `comment`
+fn syntax_errors(src: &str, comment: char) -> Vec<(usize, char)> {
+    match compile(src, Some(comment)) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors
+            .iter()
+            .map(|err| match err {
+                CompileError::Syntax(loc, ch) => (loc.index, *ch),
+                err => panic!("unexpected error {:?}", err),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn unknown_characters_are_comments_by_default() {
+    // 2 commands and halt
+    assert_eq!(compile("add one + then print .", None).unwrap(), 3);
+}
+
+#[test]
+fn strict_rejects_every_unknown_character() {
+    assert_eq!(syntax_errors("+a\n-b.", '#'), [(1, 'a'), (4, 'b')]);
+}
+
+#[test]
+fn strict_allows_whitespace_and_line_comments() {
+    assert!(syntax_errors("+ \t>\r\n# any text [ here\n-", '#').is_empty());
+    assert_eq!(compile("+# [ is not a command\n-", Some('#')).unwrap(), 3);
+}
+
+#[test]
+fn comment_character_can_be_changed() {
+    assert!(syntax_errors("+ ; comment\n-", ';').is_empty());
+    assert_eq!(syntax_errors("+ # comment", ';')[0], (2, '#'));
+}
+
+/// Location at character `index`, on `line` and `column`
+fn at(index: usize, line: usize, column: usize) -> Location {
+    Location {
+        index,
+        line,
+        column,
+    }
+}
+
+#[test]
+fn every_unmatched_bracket_is_reported_in_order() {
+    let errors = compile("]\n+[\n]]a[", Some('#')).unwrap_err();
+    let found: Vec<_> = errors
+        .iter()
+        .map(|err| match err {
+            CompileError::Syntax(loc, _) => ("syntax", *loc),
+            CompileError::UnmatchedOpen(loc, _) => ("open", *loc),
+            CompileError::UnmatchedClose(loc) => ("close", *loc),
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("close", at(0, 1, 1)),
+            ("close", at(6, 3, 2)),
+            ("syntax", at(7, 3, 3)),
+            ("open", at(8, 3, 4)),
+        ]
+    );
+}
+
+/// Where the loop body of the only unmatched `[` in `src` is guessed to end
+fn loop_end(src: &str) -> Location {
+    match &compile(src, None).unwrap_err()[..] {
+        [CompileError::UnmatchedOpen(_, end)] => *end,
+        errors => panic!("expected one unmatched '[', got {:?}", errors),
+    }
+}
+
+#[test]
+fn loop_end_follows_indentation() {
+    // body indented under the line of `[`, ending before the next line at the same depth
+    let src = "+[\n  >+\n  <-  \n\n+.\n";
+    assert_eq!(loop_end(src), at(11, 3, 4));
+
+    // nested loop: the outer body continues past the inner loop
+    let src = "  [\n    >[\n      -\n    ]\n  .\n";
+    assert_eq!(loop_end(src), at(23, 4, 5));
+}
+
+#[test]
+fn loop_end_without_indented_body_is_the_line_of_the_open() {
+    assert_eq!(loop_end("+[-> .\n+"), at(5, 1, 6));
+    assert_eq!(loop_end("+["), at(1, 1, 2));
+}
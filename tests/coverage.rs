@@ -0,0 +1,58 @@
+//! Coverage reports, of plain and preprocessed source code.
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use brainrust::*;
+
+/// Coverage of one run of `program` on empty input, stopped after `max_steps` commands
+fn coverage(program: &Program, max_steps: u64) -> (Coverage, RunResult) {
+    let coverage = Rc::new(RefCell::new(Coverage::new(program)));
+    let mut interpreter = InterpreterBuilder::from_program(program.clone(), Settings::default())
+        .observer(Box::new(coverage.clone()))
+        .input(Vec::new())
+        .build()
+        .unwrap();
+    let mut state = interpreter.ready();
+    let result = interpreter.run_for(&mut state, max_steps);
+    drop(interpreter);
+
+    (Rc::try_unwrap(coverage).ok().unwrap().into_inner(), result)
+}
+
+#[test]
+fn lcov_counts_lines_and_loops() {
+    let program = Compiler::new().compile("+\n[-]\n[>]".to_string()).unwrap();
+    let (coverage, result) = coverage(&program, COVERAGE_STEPS);
+    assert!(matches!(result, RunResult::Halted));
+    let expected = "TN:\nSF:a.bf\n\
+                    BRDA:2,0,0,1\nBRDA:2,0,1,0\nBRDA:3,1,0,0\nBRDA:3,1,1,1\nBRF:4\nBRH:2\n\
+                    DA:1,1\nDA:2,1\nDA:3,1\nLF:3\nLH:3\nend_of_record\n";
+    assert_eq!(coverage.lcov("a.bf"), expected);
+}
+
+#[test]
+fn run_stops_after_max_steps() {
+    let program = Compiler::new().compile("+\n[]\n-".to_string()).unwrap();
+    let (coverage, result) = coverage(&program, 10);
+    assert!(matches!(result, RunResult::None));
+    let expected = "TN:\nSF:a.bf\n\
+                    BRDA:2,0,0,1\nBRDA:2,0,1,0\nBRF:2\nBRH:1\n\
+                    DA:1,1\nDA:2,8\nDA:3,0\nLF:3\nLH:2\nend_of_record\n";
+    assert_eq!(coverage.lcov("a.bf"), expected);
+}
+
+#[test]
+fn lcov_of_preprocessed_source_points_into_the_original() {
+    let src = "#define CLEAR [-]\n\n+\nCLEAR\n";
+    let (src, map) = Preprocessor::new()
+        .preprocess(src, Path::new("a.bf"))
+        .unwrap();
+    let program = Compiler::new().compile(src).unwrap();
+    let (coverage, _) = coverage(&program, COVERAGE_STEPS);
+    let expected = "TN:\nSF:a.bf\n\
+                    BRDA:1,0,0,1\nBRDA:1,0,1,0\nBRF:2\nBRH:1\n\
+                    DA:1,1\nDA:3,1\nLF:2\nLH:2\nend_of_record\n";
+    assert_eq!(coverage.lcov_mapped(&map), expected);
+}
@@ -0,0 +1,227 @@
+//! Differential tests between the ways of running a program. Random balanced programs, inputs,
+//! initial tapes and settings are run on the plain interpreter, and every other engine must do the
+//! same: after a bytecode or syntax tree round trip, with observers, with loop detection, from a
+//! partially evaluated state, and minified.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use brainrust::*;
+use proptest::prelude::*;
+
+/// Commands run before giving up on a program
+const MAX_STEPS: u64 = 2_000;
+
+/// What running a program did
+struct Run {
+    output: Vec<u8>,
+    state: State,
+    /// How the program stopped, or `None` if it ran `MAX_STEPS` commands
+    result: Option<String>,
+}
+
+/// Settings picked by the tuple from `settings()`
+type SettingsSeed = (bool, usize, u8, bool, bool, bool, bool);
+
+fn settings() -> impl Strategy<Value = SettingsSeed> {
+    (
+        any::<bool>(),
+        0..8usize,
+        0..3u8,
+        any::<bool>(),
+        any::<bool>(),
+        any::<bool>(),
+        any::<bool>(),
+    )
+}
+
+fn to_settings(seed: SettingsSeed) -> Settings {
+    let (dynamic_size, array_size, eof, crlf, ignore_newline, digit, wrapping) = seed;
+    Settings {
+        dynamic_size,
+        array_size,
+        eof_behavior: match eof {
+            0 => EofBehavior::AsIs,
+            1 => EofBehavior::Zero,
+            _ => EofBehavior::NegativeOne,
+        },
+        newline_mode: if crlf {
+            NewlineMode::CRLF
+        } else {
+            NewlineMode::LF
+        },
+        ignore_newline,
+        input_mode: if digit {
+            InputMode::Digit
+        } else {
+            InputMode::Ascii
+        },
+        wrapping,
+    }
+}
+
+/// Balanced program, with loops nested up to 4 deep
+fn code() -> impl Strategy<Value = String> {
+    let commands = prop::sample::select(vec!['+', '-', '<', '>', '.', ',']);
+    let leaf = prop::collection::vec(commands, 0..8).prop_map(|code| code.into_iter().collect());
+    leaf.prop_recursive(4, 64, 4, |inner| {
+        let part = prop_oneof![inner.clone(), inner.prop_map(|body| format!("[{}]", body))];
+        prop::collection::vec(part, 1..4).prop_map(|parts| parts.concat())
+    })
+}
+
+/// Input made of bytes at the edges of reading: EOF-like values, newlines and digits
+fn input() -> impl Strategy<Value = Vec<u8>> {
+    let bytes = prop::sample::select(b"\x00\x01\n\r09a\xff".to_vec());
+    prop::collection::vec(bytes, 0..6)
+}
+
+/// Initial tape, all zeros half of the time. It may be longer than the array.
+fn tape() -> impl Strategy<Value = Vec<u8>> {
+    let bytes = prop::sample::select(vec![0, 0, 1, 2, 255]);
+    prop_oneof![Just(Vec::new()), prop::collection::vec(bytes, 0..10)]
+}
+
+fn run(mut interpreter: Interpreter, mut state: State, output: Rc<RefCell<Vec<u8>>>) -> Run {
+    let result = match interpreter.run_for(&mut state, MAX_STEPS) {
+        RunResult::None => None,
+        stopped => Some(format!("{:?}", stopped)),
+    };
+
+    let output = output.borrow().clone();
+    Run {
+        output,
+        state,
+        result,
+    }
+}
+
+/// Run `program` with the plain interpreter from the initial values `tape`, with `configure`
+/// applied to its builder.
+fn run_with(
+    program: &Program,
+    settings: &Settings,
+    tape: &[u8],
+    input: &[u8],
+    configure: impl FnOnce(InterpreterBuilder) -> InterpreterBuilder,
+) -> Run {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let builder = InterpreterBuilder::from_program(program.clone(), settings.clone())
+        .tape(tape.to_vec())
+        .input(input.to_vec())
+        .output(output.clone());
+    let interpreter = configure(builder).build().unwrap();
+    let state = interpreter.ready();
+    run(interpreter, state, output)
+}
+
+/// Assert two runs of the same program are identical.
+fn assert_same(expected: &Run, actual: &Run, engine: &str) {
+    assert_eq!(expected.output, actual.output, "output of {}", engine);
+    assert_eq!(expected.result, actual.result, "result of {}", engine);
+    assert_eq!(
+        expected.state.cells.0, actual.state.cells.0,
+        "tape of {}",
+        engine
+    );
+    assert_eq!(
+        expected.state.cell_ptr, actual.state.cell_ptr,
+        "cell pointer of {}",
+        engine
+    );
+    assert_eq!(
+        expected.state.command_ptr, actual.state.command_ptr,
+        "command pointer of {}",
+        engine
+    );
+    assert_eq!(
+        expected.state.steps, actual.state.steps,
+        "steps of {}",
+        engine
+    );
+}
+
+fn trimmed(tape: &[u8]) -> &[u8] {
+    let len = tape
+        .iter()
+        .rposition(|cell| *cell != 0)
+        .map_or(0, |last| last + 1);
+    &tape[..len]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1000))]
+
+    #[test]
+    fn engines_agree(code in code(), input in input(), tape in tape(), seed in settings()) {
+        let settings = to_settings(seed);
+        let program = Compiler::new().compile(code.clone()).unwrap();
+        let expected = run_with(&program, &settings, &tape, &input, |builder| builder);
+
+        // bytecode round trip, with the settings stored in it. Ignoring newlines depends on where
+        // input comes from, so it is not stored.
+        let (decoded, mut decoded_settings) = decode(&encode(&program, &settings).unwrap()).unwrap();
+        decoded_settings.ignore_newline = settings.ignore_newline;
+        prop_assert_eq!(&decoded.commands(), &program.commands());
+        let actual = run_with(&decoded, &decoded_settings, &tape, &input, |builder| builder);
+        assert_same(&expected, &actual, "bytecode");
+
+        // syntax tree round trip
+        let rebuilt = Program::from(&Block::from(&program));
+        prop_assert_eq!(&rebuilt.commands(), &program.commands());
+        let actual = run_with(&rebuilt, &settings, &tape, &input, |builder| builder);
+        assert_same(&expected, &actual, "syntax tree");
+
+        // observers take another path through the interpreter
+        let profiler = Rc::new(RefCell::new(Profiler::new(&program)));
+        let actual = run_with(&program, &settings, &tape, &input, |builder| {
+            builder.observer(Box::new(profiler.clone()))
+        });
+        assert_same(&expected, &actual, "observed interpreter");
+
+        // loop detection only stops programs that would never stop
+        let actual = run_with(&program, &settings, &tape, &input, |builder| builder.detect_loops());
+        match &actual.result {
+            Some(result) if result.starts_with("InfiniteLoop") => {
+                prop_assert!(
+                    expected.result.is_none(),
+                    "{} stopped as {:?}",
+                    code,
+                    expected.result
+                );
+                prop_assert!(expected.output.starts_with(&actual.output));
+            }
+            _ => assert_same(&expected, &actual, "loop detection"),
+        }
+
+        // partial evaluation, which runs on the residual program
+        let residual = partial_evaluate(&program, &settings, &tape, MAX_STEPS);
+        let output = Rc::new(RefCell::new(residual.output.clone()));
+        let builder = InterpreterBuilder::from_program(residual.program.clone(), settings.clone());
+        let interpreter = builder
+            .input(input.clone())
+            .output(output.clone())
+            .build()
+            .unwrap();
+        let actual = run(interpreter, residual.ready(), output);
+        prop_assert_eq!(&expected.output, &actual.output);
+        prop_assert_eq!(&expected.result, &actual.result);
+        prop_assert_eq!(&expected.state.cells.0, &actual.state.cells.0);
+        prop_assert_eq!(expected.state.cell_ptr, actual.state.cell_ptr);
+        prop_assert_eq!(expected.state.steps, actual.state.steps);
+        prop_assert_eq!(
+            program.locations()[expected.state.command_ptr],
+            residual.program.locations()[actual.state.command_ptr]
+        );
+
+        // minified programs run fewer commands, so only finished runs are compared
+        if expected.result.is_some() {
+            let minified = Compiler::new().compile(minify(&program, &settings, &tape)).unwrap();
+            let actual = run_with(&minified, &settings, &tape, &input, |builder| builder);
+            prop_assert_eq!(&expected.output, &actual.output);
+            prop_assert_eq!(&expected.result, &actual.result);
+            prop_assert_eq!(trimmed(&expected.state.cells.0), trimmed(&actual.state.cells.0));
+            prop_assert_eq!(expected.state.cell_ptr, actual.state.cell_ptr);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Bounded equivalence checks of small programs.
+
+use brainrust::*;
+
+fn check(a: &str, b: &str, options: &EquivOptions) -> Equivalence {
+    let a = Compiler::new().compile(a.to_string()).unwrap();
+    let b = Compiler::new().compile(b.to_string()).unwrap();
+    let settings = Settings {
+        newline_mode: NewlineMode::LF,
+        ..Settings::default()
+    };
+    check_equivalence(&a, &b, &settings, options)
+}
+
+#[test]
+fn equivalent_programs() {
+    let result = check(",.", "+-,.", &EquivOptions::default());
+    assert!(result.mismatch.is_none());
+    assert_eq!(result.inconclusive, 0);
+    // the empty input, then every byte: neither program reads a second one
+    assert_eq!(result.checked, 257);
+}
+
+#[test]
+fn programs_differing_only_on_longer_inputs() {
+    // the third byte is printed by `a` and cleared by `b`
+    let (a, b) = (",>,>,.", ",>,>,[-].");
+    let mut options = EquivOptions {
+        alphabet: vec![0, 1, 2],
+        ..EquivOptions::default()
+    };
+    assert!(check(a, b, &options).mismatch.is_none());
+
+    options.max_length = 3;
+    let mismatch = check(a, b, &options).mismatch.unwrap();
+    assert_eq!(mismatch.input, [0, 0, 1]);
+    assert_eq!(mismatch.a.output, [1]);
+    assert_eq!(mismatch.b.output, [0]);
+}
+
+#[test]
+fn step_limit_stops_runs() {
+    let options = EquivOptions {
+        max_length: 0,
+        max_steps: 1000,
+        ..EquivOptions::default()
+    };
+
+    let result = check("+[]", "+[-+]", &options);
+    assert!(result.mismatch.is_none());
+    assert_eq!((result.checked, result.inconclusive), (1, 1));
+
+    let mismatch = check("+[]", "+", &options).mismatch.unwrap();
+    assert_eq!(mismatch.a.ending, Ending::StepLimit);
+    assert_eq!(mismatch.b.ending, Ending::Halted);
+}
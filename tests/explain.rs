@@ -0,0 +1,52 @@
+//! Pseudocode of programs, with the loops the optimizer recognizes.
+
+use brainrust::*;
+
+fn explained(src: &str) -> String {
+    let program = Compiler::new().compile(src.to_string()).unwrap();
+    explain(&program, &Settings::default())
+}
+
+#[test]
+fn clear_loop() {
+    assert_eq!(explained(",[-]"), "c[0] = read();\nc[0] = 0;\n");
+    assert_eq!(explained(",[+++]"), "c[0] = read();\nc[0] = 0;\n");
+}
+
+#[test]
+fn multiply_loop() {
+    assert_eq!(
+        explained(",[->+>+++<<]"),
+        "c[0] = read();\nc[1] += c[0];\nc[2] += c[0] * 3;\nc[0] = 0;\n"
+    );
+    assert_eq!(
+        explained(">,[+<-->]"),
+        "c[1] = read();\nc[0] += c[1] * 2;\nc[1] = 0;\n"
+    );
+}
+
+#[test]
+fn move_loop() {
+    assert_eq!(
+        explained(",[>]+"),
+        "c[0] = read();\nwhile c[0] {\n    ptr += 1;\n}\nc[0] += 1;\n"
+    );
+    assert_eq!(
+        explained(">>,[<<]>+"),
+        "c[2] = read();\nptr += 2;\nwhile c[0] {\n    ptr -= 2;\n}\nc[1] += 1;\n"
+    );
+}
+
+#[test]
+fn loops_moving_on_a_dynamic_array_with_wrapping_are_kept() {
+    let program = Compiler::new().compile(",[-<+>]".to_string()).unwrap();
+    let settings = Settings {
+        dynamic_size: true,
+        wrapping: true,
+        ..Settings::default()
+    };
+    assert_eq!(
+        explain(&program, &settings),
+        "c[0] = read();\nwhile c[0] {\n    c[0] -= 1;\n    c[-1] += 1;\n}\n"
+    );
+}
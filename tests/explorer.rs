@@ -0,0 +1,163 @@
+//! Inputs generated by symbolic execution, checked by running them through the interpreter.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use brainrust::*;
+
+/// Observer recording the loop edges taken, as the index of their '[' and the kind of edge
+#[derive(Default)]
+struct Edges {
+    /// Index of the '[' matching each ']'
+    opens: Vec<usize>,
+    taken: Vec<(usize, EdgeKind)>,
+}
+
+impl Observer for Edges {
+    fn observe(&mut self, step: &Step) {
+        let edge = match step.command {
+            Command::Open(_) if step.before != 0 => (step.command_ptr, EdgeKind::Enter),
+            Command::Open(_) => (step.command_ptr, EdgeKind::Skip),
+            Command::Close(_) if step.before != 0 => {
+                (self.opens[step.command_ptr], EdgeKind::Repeat)
+            }
+            Command::Close(_) => (self.opens[step.command_ptr], EdgeKind::Exit),
+            _ => return,
+        };
+        self.taken.push(edge);
+    }
+}
+
+/// Assert every case `explore` generates for `src` reaches its target when run, and return the
+/// targets with the index of their command.
+fn explore_and_run(src: &str, settings: &Settings) -> Vec<(Target, usize)> {
+    let program = Compiler::new().compile(src.to_string()).unwrap();
+    let mut opens = vec![0; program.commands().len()];
+    for (i, command) in program.commands().iter().enumerate() {
+        if let Command::Close(open) = command {
+            opens[i] = *open;
+        }
+    }
+
+    let cases = explore(&program, settings, EXPLORE_STEPS);
+    for case in &cases {
+        let edges = Rc::new(RefCell::new(Edges {
+            opens: opens.clone(),
+            taken: Vec::new(),
+        }));
+        let mut interpreter = InterpreterBuilder::from_program(program.clone(), settings.clone())
+            .input(case.input.clone())
+            .output(Rc::new(RefCell::new(Vec::new())))
+            .observer(Box::new(edges.clone()))
+            .build()
+            .unwrap();
+        let mut state = interpreter.ready();
+        let result = interpreter.run_for(&mut state, 100_000);
+
+        let reached = match case.target {
+            Target::Loop(kind) => edges.borrow().taken.contains(&(case.command_ptr, kind)),
+            Target::IndexOutOfBound => {
+                matches!(result, RunResult::IndexOutOfBound(_))
+                    && state.command_ptr == case.command_ptr
+            }
+            Target::ParseNumError => {
+                matches!(result, RunResult::ParseNumError) && state.command_ptr == case.command_ptr
+            }
+        };
+        assert!(
+            reached,
+            "{:?} of command {} not reached by input {:?} of {}",
+            case.target, case.command_ptr, case.input, src
+        );
+        assert_eq!(case.location, program.locations()[case.command_ptr]);
+    }
+
+    cases
+        .iter()
+        .map(|case| (case.target, case.command_ptr))
+        .collect()
+}
+
+fn settings() -> Settings {
+    Settings {
+        array_size: 8,
+        newline_mode: NewlineMode::LF,
+        ..Settings::default()
+    }
+}
+
+#[test]
+fn every_edge_of_a_reading_loop() {
+    let targets = explore_and_run(",[.,]", &settings());
+    for kind in [
+        EdgeKind::Enter,
+        EdgeKind::Skip,
+        EdgeKind::Repeat,
+        EdgeKind::Exit,
+    ] {
+        assert!(targets.contains(&(Target::Loop(kind), 1)), "{:?}", kind);
+    }
+}
+
+#[test]
+fn input_compared_with_constants() {
+    // enters the loop only on 'a'
+    let src = ",>++++++++[<------------>-]<-[+>+<]";
+    let targets = explore_and_run(src, &settings());
+    assert!(targets.contains(&(Target::Loop(EdgeKind::Enter), 29)));
+    assert!(targets.contains(&(Target::Loop(EdgeKind::Skip), 29)));
+}
+
+#[test]
+fn out_of_bound_accesses() {
+    let targets = explore_and_run(",[<]+", &settings());
+    assert!(targets.contains(&(Target::IndexOutOfBound, 3)));
+
+    let targets = explore_and_run(",[>,]+", &settings());
+    assert!(targets.contains(&(Target::IndexOutOfBound, 3)));
+}
+
+#[test]
+fn moves_on_an_empty_array() {
+    let settings = Settings {
+        array_size: 0,
+        wrapping: true,
+        ..settings()
+    };
+    for src in ["<+", ">+"] {
+        let program = Compiler::new().compile(src.to_string()).unwrap();
+        let targets: Vec<(Target, usize)> = explore(&program, &settings, EXPLORE_STEPS)
+            .iter()
+            .map(|case| (case.target, case.command_ptr))
+            .collect();
+        assert_eq!(targets, [(Target::IndexOutOfBound, 1)], "{}", src);
+    }
+}
+
+#[test]
+fn number_parsing_errors() {
+    let settings = Settings {
+        input_mode: InputMode::Digit,
+        ..settings()
+    };
+    let targets = explore_and_run(",[-]", &settings);
+    assert!(targets.contains(&(Target::ParseNumError, 0)));
+}
+
+#[test]
+fn examples() {
+    for (example, input_mode) in [
+        ("cat.txt", InputMode::Ascii),
+        ("reverse.bf", InputMode::Ascii),
+        ("multiply.txt", InputMode::Digit),
+    ] {
+        let path = format!("{}/examples/{}", env!("CARGO_MANIFEST_DIR"), example);
+        let settings = Settings {
+            array_size: 30,
+            input_mode,
+            ..settings()
+        };
+        let targets = explore_and_run(&std::fs::read_to_string(path).unwrap(), &settings);
+        assert!(!targets.is_empty(), "{}", example);
+    }
+}
@@ -0,0 +1,54 @@
+//! Source code formatting, of the examples and of comments.
+
+use std::path::Path;
+
+use brainrust::*;
+
+fn compile(src: &str) -> Program {
+    Compiler::new().compile(src.to_string()).unwrap()
+}
+
+/// Format `src` with the default options
+fn format_src(src: &str) -> String {
+    format(src, &compile(src), &FormatOptions::default())
+}
+
+const EXAMPLES: [&str; 4] = [
+    "cat.txt",
+    "hello_world.txt",
+    "hello_world_shortest.txt",
+    "multiply.txt",
+];
+
+#[test]
+fn formatting_is_idempotent() {
+    for example in EXAMPLES.iter() {
+        let src = std::fs::read_to_string(Path::new("examples").join(example)).unwrap();
+        let formatted = format_src(&src);
+        assert_eq!(format_src(&formatted), formatted, "{}", example);
+    }
+}
+
+#[test]
+fn formatted_code_is_the_same_program() {
+    for example in EXAMPLES.iter() {
+        let src = std::fs::read_to_string(Path::new("examples").join(example)).unwrap();
+        let formatted = format_src(&src);
+        assert_eq!(
+            compile(&formatted).commands(),
+            compile(&src).commands(),
+            "{}",
+            example
+        );
+    }
+}
+
+#[test]
+fn comments_stay_on_their_line() {
+    assert_eq!(format_src("hello + world"), "hello +  world\n");
+    assert_eq!(format_src("+ a -"), "+ -  a\n");
+    assert_eq!(
+        format_src("own line\n+\nloop [\n-\nend ]\n"),
+        "own line\n+\nloop [\n  -\nend ]\n"
+    );
+}
@@ -0,0 +1,91 @@
+//! Generated programs, run to check that they print the text.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use brainrust::*;
+
+/// Observer noticing cells that overflow or underflow
+#[derive(Default)]
+struct Overflow {
+    overflowed: bool,
+}
+
+impl Observer for Overflow {
+    fn observe(&mut self, step: &Step) {
+        match step.command {
+            Command::Inc if step.before == 255 => self.overflowed = true,
+            Command::Dec if step.before == 0 => self.overflowed = true,
+            _ => {}
+        }
+    }
+}
+
+/// Run `code`, and return what it printed and whether a cell overflowed or underflowed
+fn run(code: &str) -> (Vec<u8>, bool) {
+    let settings = Settings {
+        newline_mode: NewlineMode::LF,
+        ..Settings::default()
+    };
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let overflow = Rc::new(RefCell::new(Overflow::default()));
+    let mut interpreter = InterpreterBuilder::new(code.to_string(), settings)
+        .input(Vec::new())
+        .output(output.clone())
+        .observer(Box::new(overflow.clone()))
+        .build()
+        .unwrap();
+    let mut state = interpreter.ready();
+    assert!(matches!(interpreter.run(&mut state), RunResult::Halted));
+
+    let printed = output.borrow().clone();
+    let overflowed = overflow.borrow().overflowed;
+    (printed, overflowed)
+}
+
+const TEXTS: [&[u8]; 4] = [b"Hello, World!\n", b"", b"aaaa", &[0, 255, 1, 254, 128]];
+
+#[test]
+fn generated_code_prints_the_text() {
+    for text in TEXTS.iter() {
+        let code = generate_text(text, &TextOptions::default());
+        assert_eq!(run(&code).0, *text, "{:?}", code);
+    }
+}
+
+#[test]
+fn non_wrapping_code_never_overflows() {
+    let options = TextOptions {
+        wrapping: false,
+        ..TextOptions::default()
+    };
+    for text in TEXTS.iter() {
+        let code = generate_text(text, &options);
+        assert_eq!(run(&code), (text.to_vec(), false), "{:?}", code);
+    }
+}
+
+#[test]
+fn fewer_cells_are_used_when_limited() {
+    let text = b"The quick brown fox jumps over the lazy dog";
+    for cells in 1..5 {
+        let options = TextOptions {
+            cells,
+            ..TextOptions::default()
+        };
+        let code = generate_text(text, &options);
+        assert_eq!(run(&code).0, text.to_vec());
+
+        let mut ptr = 0;
+        let mut used = 1;
+        for ch in code.chars() {
+            match ch {
+                '>' => ptr += 1,
+                '<' => ptr -= 1,
+                _ => {}
+            }
+            used = used.max(ptr + 1);
+        }
+        assert!(used <= cells, "{} cells: {:?}", cells, code);
+    }
+}
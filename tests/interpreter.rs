@@ -0,0 +1,107 @@
+//! Behaviour of the interpreter at the edges of the array, the input and the output.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use brainrust::*;
+
+/// Run `src` until it stops, and return how it stopped and what it wrote.
+fn run(src: &str, settings: Settings, input: &[u8]) -> (String, Vec<u8>) {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = InterpreterBuilder::new(src.to_string(), settings)
+        .input(input.to_vec())
+        .output(output.clone())
+        .build()
+        .unwrap();
+
+    let mut state = interpreter.ready();
+    let result = interpreter.run(&mut state);
+    let output = output.borrow().clone();
+    (format!("{:?}", result), output)
+}
+
+fn settings(array_size: usize, wrapping: bool) -> Settings {
+    Settings {
+        array_size,
+        wrapping,
+        newline_mode: NewlineMode::LF,
+        ..Settings::default()
+    }
+}
+
+#[test]
+fn right_from_last_cell_is_out_of_bound_without_wrapping() {
+    let (result, _) = run(">>>+", settings(3, false), b"");
+    assert_eq!(result, "IndexOutOfBound(3)");
+}
+
+#[test]
+fn right_from_last_cell_wraps_around_with_wrapping() {
+    let (result, _) = run(">>>+", settings(3, true), b"");
+    assert_eq!(result, "Halted");
+}
+
+#[test]
+fn write_outputs_the_cell_only() {
+    let (result, output) = run("+++.", settings(1, false), b"");
+    assert_eq!(result, "Halted");
+    assert_eq!(output, [3]);
+}
+
+#[test]
+fn write_outputs_newline_as_crlf() {
+    let settings = Settings {
+        newline_mode: NewlineMode::CRLF,
+        ..settings(1, false)
+    };
+    let (_, output) = run("++++++++++.", settings, b"");
+    assert_eq!(output, b"\r\n");
+}
+
+#[test]
+fn output_file_is_created() {
+    let path = std::env::temp_dir().join(format!("brainrust-output-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut interpreter = InterpreterBuilder::new("+++.".to_string(), settings(1, false))
+        .writer(path.to_str())
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut state = interpreter.ready();
+    while let RunResult::None = interpreter.next(&mut state) {}
+    drop(interpreter);
+
+    let written = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(written, [3]);
+}
+
+#[test]
+fn digit_input_wraps_around() {
+    let settings = Settings {
+        input_mode: InputMode::Digit,
+        ..settings(1, false)
+    };
+    let (result, output) = run(",.", settings, b"999\n");
+    assert_eq!(result, "Halted");
+    assert_eq!(output, [(999 % 256) as u8]);
+}
+
+#[test]
+fn left_of_dynamic_array_is_out_of_bound() {
+    let settings = Settings {
+        dynamic_size: true,
+        ..settings(1, false)
+    };
+    let (result, _) = run("<+", settings, b"");
+    assert_eq!(result, format!("IndexOutOfBound({})", usize::MAX));
+}
+
+#[test]
+fn moves_on_an_empty_array_are_out_of_bound() {
+    let (result, _) = run("<+", settings(0, true), b"");
+    assert_eq!(result, format!("IndexOutOfBound({})", usize::MAX));
+    let (result, _) = run(">+", settings(0, true), b"");
+    assert_eq!(result, "IndexOutOfBound(1)");
+}
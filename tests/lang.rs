@@ -0,0 +1,182 @@
+//! Programs in the high-level language, compiled to Brainfuck and checked by running them.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use brainrust::*;
+
+/// Run the compiled code of `src` at the end of the input leaving the cell as is and setting it
+/// to 0, checking it prints the same as running `src` directly
+fn run(src: &str, input: &[u8]) -> Vec<u8> {
+    let code = compile_lang(src).unwrap();
+    let (expected, finished) = run_lang(src, input, LANG_STEPS).unwrap();
+    assert!(finished);
+
+    for (name, eof_behavior) in [("as_is", EofBehavior::AsIs), ("zero", EofBehavior::Zero)] {
+        let settings = Settings {
+            newline_mode: NewlineMode::LF,
+            eof_behavior,
+            ..Settings::default()
+        };
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = InterpreterBuilder::new(code.clone(), settings)
+            .input(input.to_vec())
+            .output(output.clone())
+            .build()
+            .unwrap();
+        let mut state = interpreter.ready();
+        assert!(matches!(interpreter.run(&mut state), RunResult::Halted));
+        assert_eq!(*output.borrow(), expected, "{}", name);
+    }
+    expected
+}
+
+#[test]
+fn print_and_read() {
+    let src = r#"
+        var c;
+        print "Hi\n";
+        read c;
+        while c {
+            print c + 1;
+            read c;
+        }
+    "#;
+    assert_eq!(run(src, b"HAL"), b"Hi\nIBM");
+}
+
+#[test]
+fn arithmetic() {
+    for (a, b) in [
+        (0u8, 0u8),
+        (7, 3),
+        (200, 7),
+        (3, 200),
+        (255, 255),
+        (13, 0),
+        (1, 1),
+    ] {
+        let src = format!(
+            "var a = {}; var b = {}; print a + b; print a - b; print a * b; print a / b; print a % b;",
+            a, b
+        );
+        let expected = [
+            a.wrapping_add(b),
+            a.wrapping_sub(b),
+            a.wrapping_mul(b),
+            a.checked_div(b).unwrap_or(0),
+            a.checked_rem(b).unwrap_or(a),
+        ];
+        assert_eq!(run(&src, b""), expected, "a = {}, b = {}", a, b);
+    }
+}
+
+#[test]
+fn comparisons() {
+    for (a, b) in [
+        (0u8, 0u8),
+        (0, 1),
+        (1, 0),
+        (5, 5),
+        (254, 255),
+        (255, 254),
+        (128, 3),
+    ] {
+        let src = format!(
+            "var a = {}; var b = {};
+            print a == b; print a != b; print a < b; print a <= b; print a > b; print a >= b;
+            print a && b; print a || b; print !a;",
+            a, b
+        );
+        let expected: Vec<u8> = [
+            a == b,
+            a != b,
+            a < b,
+            a <= b,
+            a > b,
+            a >= b,
+            a != 0 && b != 0,
+            a != 0 || b != 0,
+            a == 0,
+        ]
+        .iter()
+        .map(|result| *result as u8)
+        .collect();
+        assert_eq!(run(&src, b""), expected, "a = {}, b = {}", a, b);
+    }
+}
+
+#[test]
+fn control_flow() {
+    let src = r#"
+        var i = 0;
+        while i < 10 {
+            if i % 3 == 0 {
+                print "f";
+            } else if i % 3 == 1 {
+                print "b";
+            } else {
+                print i + '0';
+            }
+            i = i + 1;
+        }
+    "#;
+    assert_eq!(run(src, b""), b"fb2fb5fb8f");
+}
+
+#[test]
+fn multiply() {
+    // same as examples/multiply.txt, with digits in and out
+    let src = r#"
+        var a; var b;
+        read a; read b;
+        var product = (a - '0') * (b - '0');
+        if product >= 10 {
+            print product / 10 + '0';
+        }
+        print product % 10 + '0';
+    "#;
+    assert_eq!(run(src, b"67"), b"42");
+    assert_eq!(run(src, b"30"), b"0");
+}
+
+#[test]
+fn errors() {
+    assert!(matches!(
+        compile_lang("print x;"),
+        Err(LangError::UndefinedVariable(_, _))
+    ));
+    assert!(matches!(
+        compile_lang("var x; var x;"),
+        Err(LangError::RedefinedVariable(_, _))
+    ));
+    assert!(matches!(
+        compile_lang("var x = 256;"),
+        Err(LangError::InvalidNumber(_, _))
+    ));
+    assert!(matches!(
+        compile_lang("var x = 1"),
+        Err(LangError::Expected(_, ";"))
+    ));
+    assert!(matches!(
+        compile_lang("print \"abc"),
+        Err(LangError::InvalidLiteral(_))
+    ));
+}
+
+#[test]
+fn direct_run_stops_after_max_steps() {
+    let (output, finished) = run_lang("while 1 { print 'a'; }", b"", 7).unwrap();
+    assert!(!finished);
+    assert_eq!(output, b"aaa");
+
+    let (_, finished) = run_lang("var a = 1; while a {}", b"", 1000).unwrap();
+    assert!(!finished);
+}
+
+#[test]
+fn example() {
+    let path = format!("{}/examples/multiply.bfl", env!("CARGO_MANIFEST_DIR"));
+    let src = std::fs::read_to_string(path).unwrap();
+    assert_eq!(run(&src, b"67"), b"42\n");
+}
@@ -0,0 +1,55 @@
+//! Lint warnings, one kind at a time.
+
+use brainrust::*;
+
+/// Kinds of the warnings for `src`, with their columns
+fn lint(src: &str) -> Vec<(usize, WarningKind)> {
+    let program = Compiler::new().compile(src.to_string()).unwrap();
+    brainrust::lint(&program)
+        .into_iter()
+        .map(|warning| (warning.location.column, warning.kind))
+        .collect()
+}
+
+#[test]
+fn drifting_loop() {
+    assert_eq!(lint(",[>]"), vec![(2, WarningKind::DriftingLoop(1))]);
+}
+
+#[test]
+fn infinite_loop() {
+    assert_eq!(lint("+[.]"), vec![(2, WarningKind::InfiniteLoop)]);
+}
+
+#[test]
+fn unreachable() {
+    assert_eq!(
+        lint("+[]+"),
+        vec![
+            (2, WarningKind::InfiniteLoop),
+            (4, WarningKind::Unreachable)
+        ]
+    );
+}
+
+#[test]
+fn unguarded_read() {
+    assert_eq!(lint(",."), vec![(1, WarningKind::UnguardedRead)]);
+    assert_eq!(lint("+[-],."), vec![]);
+    assert_eq!(lint(",[.,]"), vec![]);
+}
+
+#[test]
+fn cancelling_pair() {
+    assert_eq!(lint("+-"), vec![(1, WarningKind::CancellingPair('+', '-'))]);
+}
+
+#[test]
+fn dead_loop() {
+    assert_eq!(lint("+[-][.]"), vec![(5, WarningKind::DeadLoop)]);
+}
+
+#[test]
+fn leading_comment_block_is_not_reported() {
+    assert_eq!(lint("[comment]\n+[-]"), vec![]);
+}
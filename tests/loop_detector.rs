@@ -0,0 +1,85 @@
+//! Detection of infinite loops that repeat the same state without I/O.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use brainrust::*;
+
+/// Run `src` with loop detection for at most `max_steps` commands, and return how it stopped,
+/// or `None` if it was still running.
+fn run(src: &str, settings: Settings, max_steps: u64) -> Option<RunResult> {
+    let mut interpreter = InterpreterBuilder::new(src.to_string(), settings)
+        .input(Vec::new())
+        .output(Rc::new(RefCell::new(Vec::new())))
+        .detect_loops()
+        .build()
+        .unwrap();
+    let mut state = interpreter.ready();
+    while state.steps < max_steps {
+        match interpreter.next(&mut state) {
+            RunResult::None => continue,
+            result => return Some(result),
+        }
+    }
+    None
+}
+
+fn fixed() -> Settings {
+    Settings {
+        array_size: 8,
+        ..Settings::default()
+    }
+}
+
+/// Index of the '[' an infinite loop was reported at
+fn loop_at(result: Option<RunResult>) -> Option<usize> {
+    match result {
+        Some(RunResult::InfiniteLoop { location }) => Some(location.index),
+        _ => None,
+    }
+}
+
+#[test]
+fn empty_loop_is_detected() {
+    assert_eq!(loop_at(run("+[]", fixed(), 1_000)), Some(1));
+}
+
+#[test]
+fn loop_changing_cells_is_detected_once_they_wrap_around() {
+    assert_eq!(loop_at(run("+[>+<]", fixed(), 100_000)), Some(1));
+}
+
+#[test]
+fn inner_loop_is_reported() {
+    assert_eq!(loop_at(run("+[>+[]<-]", fixed(), 1_000)), Some(4));
+}
+
+#[test]
+fn loops_that_stop_are_not_detected() {
+    assert!(matches!(
+        run("+++[>++[>+<-]<-]", fixed(), 100_000),
+        Some(RunResult::Halted)
+    ));
+}
+
+#[test]
+fn loop_reading_input_is_not_detected() {
+    // every read is I/O, even at the end of the input
+    assert_eq!(run("+[,]", fixed(), 100_000).map(|_| ()), None);
+}
+
+#[test]
+fn loop_writing_output_is_not_detected() {
+    assert_eq!(run("+[.]", fixed(), 100_000).map(|_| ()), None);
+}
+
+#[test]
+fn loop_moving_on_dynamic_array_is_not_detected() {
+    let settings = Settings {
+        dynamic_size: true,
+        array_size: 1,
+        ..Settings::default()
+    };
+    // the array grows on every iteration, so no state repeats
+    assert_eq!(run("+[>+]", settings, 100_000).map(|_| ()), None);
+}
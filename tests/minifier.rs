@@ -0,0 +1,52 @@
+//! Minified programs, for the settings and the initial tape they are run with.
+
+use brainrust::*;
+
+fn minified(src: &str, settings: &Settings, tape: &[u8]) -> String {
+    let program = Compiler::new().compile(src.to_string()).unwrap();
+    minify(&program, settings, tape)
+}
+
+#[test]
+fn cancelling_commands_are_removed() {
+    let settings = Settings::default();
+    assert_eq!(minified("+-+>><<< comment", &settings, &[]), "+<");
+    assert_eq!(minified("[-]++++[>+<-]", &settings, &[]), "++++[>+<-]");
+}
+
+#[test]
+fn accesses_that_may_fail_out_of_bounds_are_kept() {
+    let settings = Settings {
+        array_size: 2,
+        ..Settings::default()
+    };
+    assert_eq!(minified("+-<+->", &settings, &[]), "<+->");
+    assert_eq!(minified("<[-]", &settings, &[]), "<[-]");
+    assert_eq!(minified(">[-]>[.]", &settings, &[]), ">[-]>[.]");
+
+    let empty = Settings {
+        array_size: 0,
+        ..Settings::default()
+    };
+    assert_eq!(minified("+-[.]", &empty, &[]), "+-[.]");
+}
+
+#[test]
+fn moves_are_kept_on_dynamic_array_with_wrapping() {
+    let settings = Settings {
+        dynamic_size: true,
+        wrapping: true,
+        ..Settings::default()
+    };
+    assert_eq!(minified("+<>+", &settings, &[]), "+<>+");
+    assert_eq!(minified(">>><<", &settings, &[]), ">>><<");
+    assert_eq!(minified("+[-<+>]", &settings, &[]), "+[-<+>]");
+}
+
+#[test]
+fn leading_loops_are_kept_with_initial_tape() {
+    let settings = Settings::default();
+    assert_eq!(minified("[-][.]>+", &settings, &[]), ">+");
+    assert_eq!(minified("[-][.]>+", &settings, &[0, 0]), ">+");
+    assert_eq!(minified("[-][.]>+", &settings, &[3]), "[-]>+");
+}
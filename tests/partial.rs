@@ -0,0 +1,86 @@
+//! Partial evaluation of the part of programs before their first input.
+
+use brainrust::*;
+
+fn compile(src: &str) -> Program {
+    Compiler::new().compile(src.to_string()).unwrap()
+}
+
+fn settings() -> Settings {
+    Settings {
+        array_size: 4,
+        newline_mode: NewlineMode::LF,
+        ..Settings::default()
+    }
+}
+
+#[test]
+fn stops_at_the_first_read_outside_loops() {
+    let program = compile("+++.>+,.");
+    let residual = partial_evaluate(&program, &settings(), &[], PARTIAL_STEPS);
+
+    assert_eq!(residual.output, [3]);
+    assert_eq!(residual.program.commands(), compile(",.").commands());
+    assert_eq!(residual.program.locations(), &program.locations()[6..]);
+    assert_eq!(residual.state.command_ptr, 0);
+    assert_eq!(residual.state.cell_ptr, 1);
+    assert_eq!(residual.state.cells.0, [3, 1, 0, 0]);
+}
+
+#[test]
+fn loops_after_the_read_are_rebased() {
+    let program = compile("+.,[->+<]");
+    let residual = partial_evaluate(&program, &settings(), &[], PARTIAL_STEPS);
+
+    assert_eq!(residual.program.commands(), compile(",[->+<]").commands());
+    assert!(matches!(residual.program.commands()[1], Command::Open(6)));
+    assert!(matches!(residual.program.commands()[6], Command::Close(1)));
+}
+
+#[test]
+fn stopping_inside_a_loop_keeps_the_whole_program() {
+    let program = compile("++[>,<-]");
+    let residual = partial_evaluate(&program, &settings(), &[], PARTIAL_STEPS);
+
+    assert_eq!(residual.program.commands(), program.commands());
+    assert_eq!(residual.state.command_ptr, 4);
+    assert_eq!(residual.state.cell_ptr, 1);
+    assert_eq!(residual.state.cells.0, [2, 0, 0, 0]);
+}
+
+#[test]
+fn programs_without_input_leave_only_their_output() {
+    let program = compile("++++++++[>++++++++<-]>+.+.");
+    let residual = partial_evaluate(&program, &settings(), &[], PARTIAL_STEPS);
+
+    assert_eq!(residual.output, b"AB");
+    assert_eq!(residual.program.commands(), [Command::Halt]);
+}
+
+#[test]
+fn initial_tape_is_used() {
+    let program = compile("[>+<-]>.,");
+    let residual = partial_evaluate(&program, &settings(), &[5], PARTIAL_STEPS);
+
+    assert_eq!(residual.output, [5]);
+    assert_eq!(residual.program.commands(), compile(",").commands());
+}
+
+#[test]
+fn failing_command_is_left_to_run() {
+    let program = compile("+<+,");
+    let residual = partial_evaluate(&program, &settings(), &[], PARTIAL_STEPS);
+
+    assert_eq!(residual.program.commands(), compile("+,").commands());
+    assert_eq!(residual.state.cell_ptr, usize::MAX);
+    assert_eq!(residual.state.cells.0, [1, 0, 0, 0]);
+}
+
+#[test]
+fn long_runs_stop_after_max_steps() {
+    let program = compile("+[]");
+    let residual = partial_evaluate(&program, &settings(), &[], 100);
+
+    assert_eq!(residual.state.steps, 100);
+    assert_eq!(residual.program.commands(), program.commands());
+}
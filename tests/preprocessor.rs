@@ -0,0 +1,135 @@
+//! Macro preprocessor: includes, macros and repeats.
+
+use std::path::{Path, PathBuf};
+
+use brainrust::*;
+
+/// Empty directory for the files of one test
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "brainrust-preprocessor-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn preprocess(src: &str) -> Result<String, PreprocessError> {
+    let (src, _) = Preprocessor::new().preprocess(src, Path::new("main.bf"))?;
+    Ok(src)
+}
+
+/// Commands of `src`, without comments and whitespace
+fn commands(src: &str) -> String {
+    src.chars().filter(|ch| "+-<>[],.".contains(*ch)).collect()
+}
+
+#[test]
+fn module_example() {
+    // the example at the top of src/preprocessor.rs
+    let module = include_str!("../src/preprocessor.rs");
+    let example: Vec<&str> = module
+        .lines()
+        .skip_while(|line| *line != "//! ```text")
+        .skip(1)
+        .take_while(|line| *line != "//! ```")
+        .map(|line| line.trim_start_matches("//!").trim_start_matches(' '))
+        .collect();
+    assert!(!example.is_empty());
+
+    let dir = dir("example");
+    std::fs::create_dir(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib/copy.bf"), "[->+<]\n").unwrap();
+    let path = dir.join("main.bf");
+    std::fs::write(&path, example.join("\n")).unwrap();
+
+    let (src, _) = Preprocessor::new().preprocess_file(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(commands(&src), "[->+<][-]>>++++++++++<<");
+}
+
+#[test]
+fn repeats() {
+    assert_eq!(preprocess("{+}*3 {>{-}*2}*2").unwrap(), "+++ >-->--");
+    assert_eq!(preprocess("#define N 4\n{<}*N").unwrap(), "\n<<<<");
+    assert_eq!(preprocess("{+}*0.").unwrap(), ".");
+}
+
+#[test]
+fn braces_without_count_are_kept() {
+    assert_eq!(
+        preprocess("{code}*count {a} }{ {").unwrap(),
+        "{code}*count {a} }{ {"
+    );
+    assert!(matches!(
+        preprocess("#define N x\n{+}*N"),
+        Err(PreprocessError::InvalidRepeat(_, _))
+    ));
+}
+
+#[test]
+fn repeats_are_limited() {
+    assert!(matches!(
+        preprocess("{+}*99999999999"),
+        Err(PreprocessError::RepeatTooLarge(_, _))
+    ));
+    assert!(matches!(
+        preprocess("{+}*999999999999999999999999"),
+        Err(PreprocessError::RepeatTooLarge(_, _))
+    ));
+    assert!(matches!(
+        preprocess("{{{+}*1000}*1000}*1000"),
+        Err(PreprocessError::RepeatTooLarge(_, _))
+    ));
+    assert_eq!(
+        preprocess(&format!("{{+}}*{}", MAX_REPEAT)).unwrap().len(),
+        MAX_REPEAT
+    );
+}
+
+#[test]
+fn empty_repeats_take_any_count() {
+    assert_eq!(preprocess("{}*99999999999999999999+").unwrap(), "+");
+    assert_eq!(preprocess("#define E\n{E}*99999999999+").unwrap(), "\n+");
+}
+
+#[test]
+fn expansions_are_limited_in_total() {
+    let mut src = String::from("#define M0 +\n");
+    for i in 1..40 {
+        src.push_str(&format!("#define M{} M{} M{}\n", i, i - 1, i - 1));
+    }
+    src.push_str("M39");
+    assert!(matches!(
+        preprocess(&src),
+        Err(PreprocessError::ExpansionTooLarge(_, _))
+    ));
+
+    let src = format!("#define R {{+}}*{}\n{{R}}*100", MAX_REPEAT / 100);
+    assert!(preprocess(&src).is_ok());
+    let src = format!("#define R {{+}}*{}\n{}", MAX_REPEAT, "R ".repeat(20));
+    assert!(matches!(
+        preprocess(&src),
+        Err(PreprocessError::ExpansionTooLarge(_, _))
+    ));
+}
+
+#[test]
+fn errors_point_into_the_original_file() {
+    let dir = dir("errors");
+    std::fs::write(dir.join("lib.bf"), "+\n  {+}*N\n").unwrap();
+    let path = dir.join("main.bf");
+    std::fs::write(&path, "#define N no\n#include \"lib.bf\"\n").unwrap();
+
+    let result = Preprocessor::new().preprocess_file(&path);
+    std::fs::remove_dir_all(&dir).unwrap();
+    match result {
+        Err(PreprocessError::InvalidRepeat(file, loc)) => {
+            assert_eq!(file, dir.join("lib.bf"));
+            assert_eq!((loc.line, loc.column), (2, 3));
+        }
+        result => panic!("expected an invalid repeat, got {:?}", result.map(|_| ())),
+    }
+}
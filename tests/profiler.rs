@@ -0,0 +1,109 @@
+//! Profiling a program with nested loops.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use brainrust::*;
+
+/// Outer loop running twice, inner loop running three times for each
+const SRC: &str = "++[\n>+++[>+<-]\n<-]\n.";
+
+fn profile(src: &str) -> Profiler {
+    let program = Compiler::new().compile(src.to_string()).unwrap();
+    let profiler = Rc::new(RefCell::new(Profiler::new(&program)));
+    let mut interpreter = InterpreterBuilder::from_program(program, Settings::default())
+        .input(Vec::new())
+        .observer(Box::new(profiler.clone()))
+        .build()
+        .unwrap();
+    let mut state = interpreter.ready();
+    while let RunResult::None = interpreter.next(&mut state) {}
+    drop(interpreter);
+    Rc::try_unwrap(profiler).ok().unwrap().into_inner()
+}
+
+#[test]
+fn hits_and_total() {
+    let profiler = profile(SRC);
+    assert_eq!(
+        profiler.hits,
+        [1, 1, 1, 2, 2, 2, 2, 2, 6, 6, 6, 6, 6, 2, 2, 2, 1, 0]
+    );
+    assert_eq!(profiler.total(), 50);
+}
+
+#[test]
+fn loops_hottest_first() {
+    let loops = profile(SRC).loops();
+    let found: Vec<_> = loops
+        .iter()
+        .map(|profile| {
+            (
+                profile.open.to_string(),
+                profile.close.to_string(),
+                profile.entries,
+                profile.iterations,
+                profile.steps,
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("1:3".to_string(), "3:3".to_string(), 1, 2, 47),
+            ("2:5".to_string(), "2:10".to_string(), 2, 6, 32),
+        ]
+    );
+}
+
+#[test]
+fn report() {
+    assert_eq!(
+        profile(SRC).report(1),
+        concat!(
+            "Total steps: 50\n",
+            "Hottest loops:\n",
+            "         steps       %     iterations    entries  source\n",
+            "            47  94.00%              2          1  1:3-3:3\n",
+        )
+    );
+}
+
+#[test]
+fn annotate() {
+    assert_eq!(
+        profile(SRC).annotate(SRC),
+        concat!(
+            "             3 | ++[\n",
+            "            40 | >+++[>+<-]\n",
+            "             6 | <-]\n",
+            "             1 | .\n",
+        )
+    );
+}
+
+#[test]
+fn lines_without_commands_are_blank() {
+    let src = "comment\r\n+";
+    assert_eq!(
+        profile(src).annotate(src),
+        "               | comment\n             1 | +\n"
+    );
+}
+
+#[test]
+fn folded_stacks_nest_loops() {
+    assert_eq!(
+        profile(SRC).folded(),
+        concat!(
+            "main 3\n",
+            "main;[1:3-3:3] 15\n",
+            "main;[1:3-3:3];[2:5-2:10] 32\n",
+        )
+    );
+}
+
+#[test]
+fn skipped_loop_counts_in_its_parent() {
+    assert_eq!(profile("[-]+").folded(), "main 2\n");
+}
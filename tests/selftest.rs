@@ -0,0 +1,16 @@
+//! The conformance suite of `brainrust selftest` passes under every combination of settings it checks.
+
+use brainrust::*;
+
+#[test]
+fn conformance() {
+    let (nonconformances, runs) = selftest();
+    assert_eq!(
+        runs,
+        conformance_suite().len() * conformance_settings().len()
+    );
+    for nonconformance in &nonconformances {
+        eprintln!("{}: {:?}", nonconformance.name, nonconformance.failures);
+    }
+    assert!(nonconformances.is_empty());
+}
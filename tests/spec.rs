@@ -0,0 +1,171 @@
+//! Test specs: parsing spec files and running their cases.
+
+use brainrust::*;
+
+fn compile(src: &str) -> Program {
+    Compiler::new().compile(src.to_string()).unwrap()
+}
+
+/// Failures of every case of `spec` run on `src`
+fn run(src: &str, spec: &Spec) -> Vec<Vec<Failure>> {
+    let program = compile(src);
+    spec.cases
+        .iter()
+        .map(|case| spec.run(&program, case))
+        .collect()
+}
+
+#[test]
+fn settings_and_cases() {
+    let spec = Spec::parse(
+        r#"
+        [settings]
+        array_size = 10
+        eof_behavior = "zero"
+        newline_mode = "LF"
+        input_mode = "digit"
+        max_steps = 100
+
+        [[case]]
+        input = "abc"
+        output = [1, 2]
+        tape = [0, 99]
+        result = "infinite_loop"
+
+        [[case]]
+        name = "bytes"
+        input = [255, 0]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(spec.settings.array_size, 10);
+    assert!(matches!(spec.settings.eof_behavior, EofBehavior::Zero));
+    assert!(matches!(spec.settings.newline_mode, NewlineMode::LF));
+    assert!(matches!(spec.settings.input_mode, InputMode::Digit));
+    assert!(!spec.settings.ignore_newline);
+    assert_eq!(spec.max_steps, 100);
+
+    let first = &spec.cases[0];
+    assert_eq!(first.name, "case 1");
+    assert_eq!(first.input, b"abc");
+    assert_eq!(first.output.as_deref(), Some(&[1, 2][..]));
+    assert_eq!(first.tape.as_deref(), Some(&[0, 99][..]));
+    assert_eq!(first.result, Ending::InfiniteLoop);
+
+    let second = &spec.cases[1];
+    assert_eq!(second.name, "bytes");
+    assert_eq!(second.input, [255, 0]);
+    assert_eq!(second.output, None);
+    assert_eq!(second.tape, None);
+    assert_eq!(second.result, Ending::Halted);
+}
+
+#[test]
+fn invalid_settings_are_rejected() {
+    for (src, name, value) in [
+        (
+            "[settings]\neof_behavior = \"never\"",
+            "eof_behavior",
+            "never",
+        ),
+        ("[settings]\nnewline_mode = \"lf\"", "newline_mode", "lf"),
+        ("[settings]\ninput_mode = \"hex\"", "input_mode", "hex"),
+    ] {
+        match Spec::parse(src) {
+            Err(SpecError::InvalidSetting(setting, val)) => {
+                assert_eq!((setting, val.as_str()), (name, value))
+            }
+            result => panic!("{:?} parsed to {:?}", src, result.map(|_| ())),
+        }
+    }
+
+    assert!(matches!(
+        Spec::parse("[[case]]\nresult = \"crashed\""),
+        Err(SpecError::InvalidResult(result)) if result == "crashed"
+    ));
+}
+
+#[test]
+fn unknown_and_mistyped_fields_are_rejected() {
+    for src in [
+        "[settings]\ncolour = 1",
+        "[[case]]\nexpected = \"a\"",
+        "[other]",
+        "[settings]\narray_size = \"10\"",
+        "[[case]]\ninput = 3",
+        "[[case]]\ninput = [256]",
+    ] {
+        assert!(
+            matches!(Spec::parse(src), Err(SpecError::Toml(_))),
+            "{:?}",
+            src
+        );
+    }
+}
+
+#[test]
+fn tape_is_compared_as_a_prefix() {
+    let spec = Spec::parse(
+        "[settings]\narray_size = 4\n\
+         [[case]]\ntape = [1, 2]\n\
+         [[case]]\ntape = [1, 2, 0, 0, 0, 0]\n\
+         [[case]]\ntape = [1, 3]",
+    )
+    .unwrap();
+    let failures = run("+>++", &spec);
+
+    assert!(failures[0].is_empty());
+    assert!(failures[1].is_empty());
+    match &failures[2][..] {
+        [Failure::Tape { expected, actual }] => {
+            assert_eq!(expected, &[1, 3]);
+            assert_eq!(actual, &[1, 2]);
+        }
+        failures => panic!("expected a tape failure, got {:?}", failures),
+    }
+}
+
+#[test]
+fn output_and_result_are_compared() {
+    let spec = Spec::parse(
+        "[settings]\narray_size = 4\nmax_steps = 1000\n\
+         [[case]]\ninput = \"a\"\noutput = \"a\"\n\
+         [[case]]\ninput = \"a\"\noutput = \"b\"\nresult = \"infinite_loop\"\n\
+         [[case]]\ninput = [0]\nresult = \"step_limit\"",
+    )
+    .unwrap();
+    let failures = run(",.", &spec);
+
+    assert!(failures[0].is_empty());
+    assert!(matches!(
+        &failures[1][..],
+        [
+            Failure::Output { .. },
+            Failure::Result {
+                expected: Ending::InfiniteLoop,
+                actual: Ending::Halted
+            }
+        ]
+    ));
+    assert!(matches!(
+        &failures[2][..],
+        [Failure::Result {
+            expected: Ending::StepLimit,
+            actual: Ending::Halted
+        }]
+    ));
+}
+
+#[test]
+fn reverse_example() {
+    let examples = concat!(env!("CARGO_MANIFEST_DIR"), "/examples");
+    let src = std::fs::read_to_string(format!("{}/reverse.bf", examples)).unwrap();
+    let spec = Spec::parse(&std::fs::read_to_string(format!("{}/reverse.toml", examples)).unwrap())
+        .unwrap();
+
+    assert_eq!(spec.cases.len(), 3);
+    for (case, failures) in spec.cases.iter().zip(run(&src, &spec)) {
+        assert!(failures.is_empty(), "{}: {:?}", case.name, failures);
+    }
+}
@@ -0,0 +1,63 @@
+//! Execution traces in both formats, written to a file.
+
+use std::fs::File;
+
+use brainrust::*;
+
+/// Trace of running `src` in `format`
+fn trace(name: &str, src: &str, format: TraceFormat) -> Vec<u8> {
+    let path =
+        std::env::temp_dir().join(format!("brainrust-trace-{}-{}", std::process::id(), name));
+    let tracer = Tracer::new(Box::new(File::create(&path).unwrap()), format);
+    let mut interpreter = InterpreterBuilder::new(src.to_string(), Settings::default())
+        .input(Vec::new())
+        .observer(Box::new(tracer))
+        .build()
+        .unwrap();
+    let mut state = interpreter.ready();
+    while let RunResult::None = interpreter.next(&mut state) {}
+    interpreter.finish().unwrap();
+    // the tracer, and its file, are closed with the interpreter
+    drop(interpreter);
+
+    let trace = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    trace
+}
+
+#[test]
+fn json_lines() {
+    let trace = trace("json", "+>\n-<", TraceFormat::JsonLines);
+    let lines: Vec<&str> = std::str::from_utf8(&trace).unwrap().lines().collect();
+    assert_eq!(
+        lines,
+        [
+            r#"{"step":0,"command_ptr":0,"command":"+","line":1,"column":1,"cell_ptr":0,"before":0,"after":1}"#,
+            r#"{"step":1,"command_ptr":1,"command":">","line":1,"column":2,"cell_ptr":0,"before":1,"after":1}"#,
+            r#"{"step":2,"command_ptr":2,"command":"-","line":2,"column":1,"cell_ptr":1,"before":0,"after":255}"#,
+            r#"{"step":3,"command_ptr":3,"command":"<","line":2,"column":2,"cell_ptr":1,"before":255,"after":255}"#,
+        ]
+    );
+}
+
+/// Binary record of a step
+fn record(step: u64, command_ptr: u64, column: u64, cell_ptr: u64, command: u8) -> Vec<u8> {
+    let mut record = Vec::new();
+    for val in [step, command_ptr, 1, column, cell_ptr] {
+        record.extend_from_slice(&val.to_le_bytes());
+    }
+    record.extend_from_slice(&[command, 0, 0]);
+    record
+}
+
+#[test]
+fn binary_keeps_pointers_past_the_array() {
+    // the second '<' runs with the cell pointer moved before the first cell
+    let trace = trace("binary", "<<", TraceFormat::Binary);
+
+    let mut expected = b"BFT\0".to_vec();
+    expected.extend_from_slice(&1u16.to_le_bytes());
+    expected.extend(record(0, 0, 1, 0, b'<'));
+    expected.extend(record(1, 1, 2, u64::MAX, b'<'));
+    assert_eq!(trace, expected);
+}